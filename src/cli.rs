@@ -0,0 +1,1048 @@
+//! Command line interface.
+//!
+//! Every action is a subcommand with its own arguments. The flags of earlier releases
+//! (`-s`, `-q`, `-l`, `-d`, `--clear`, `--init`, ...) are still accepted as hidden
+//! aliases so that existing scripts keep working, and running `komando` without a
+//! subcommand still saves the last command.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::PathBuf;
+
+use crate::config::SETTINGS;
+use crate::db::{DedupPolicy, ListFilter, Page, SearchMode, SortOrder};
+use crate::output::Format;
+use crate::picker::Height;
+
+/// What komando was asked to do.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Save(SaveArgs),
+    Add(AddArgs),
+    Search(SearchArgs),
+    List(ListArgs),
+    ListTags,
+    /// Set the description of a command; `None` prompts for it
+    Describe {
+        id: String,
+        description: Option<String>,
+    },
+    Label(LabelArgs),
+    Edit {
+        id: String,
+    },
+    Show {
+        id: String,
+        format: Option<Format>,
+    },
+    Remove {
+        id: String,
+    },
+    Clear,
+    Init,
+    Migrate {
+        file: Option<PathBuf>,
+        dry_run: bool,
+    },
+    Check {
+        repair: bool,
+    },
+    Config(ConfigAction),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigAction {
+    /// Print every setting with its value and where it comes from
+    List,
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: String,
+    },
+    /// Print the location of the config file
+    Path,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SaveArgs {
+    /// Number of recent history entries to pick from; `None` saves the last command
+    pub count: Option<usize>,
+    /// `None` uses the configured height of the picker
+    pub height: Option<Height>,
+    pub options: SaveOptions,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AddArgs {
+    /// The command to save, or `None` to read it from stdin
+    pub command: Option<String>,
+    /// Working directory to save, instead of the current one
+    pub directory: Option<PathBuf>,
+    pub options: SaveOptions,
+}
+
+/// How a command is saved, whether it comes from the shell history or not.
+#[derive(Debug, PartialEq)]
+pub struct SaveOptions {
+    /// `Some("")` means the flag was given without text: prompt for it
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub group: Option<String>,
+    /// `None` uses the configured policy
+    pub dedup: Option<DedupPolicy>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SearchArgs {
+    pub query: String,
+    /// `None` uses the configured mode
+    pub mode: Option<SearchMode>,
+    pub filter: ListFilter,
+    pub show_scores: bool,
+    /// `None` uses the configured height of the picker
+    pub height: Option<Height>,
+    /// Print the results in this format instead of opening the picker
+    pub format: Option<Format>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ListArgs {
+    pub filter: ListFilter,
+    /// `None` uses the configured order
+    pub sort: Option<SortOrder>,
+    pub page: Page,
+    pub format: Option<Format>,
+}
+
+/// Changes to the tags and group of a saved command.
+#[derive(Debug, PartialEq)]
+pub struct LabelArgs {
+    pub id: String,
+    pub tags: Vec<String>,
+    pub untags: Vec<String>,
+    pub group: Option<String>,
+    pub ungroup: bool,
+}
+
+pub fn command() -> Command {
+    Command::new("Komando")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Noureddine Gueddach")
+        .about("A command line utility to better organize and keep track of your commands.")
+        .after_help("Running komando without a subcommand saves the last command.")
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("save")
+                .about("Save the last command")
+                .args(save_args()),
+        )
+        .subcommand(
+            Command::new("add")
+                .about("Save a command given as arguments or on stdin")
+                .arg(
+                    Arg::new("command")
+                        .value_name("COMMAND")
+                        .help(
+                            "Command to save, usually after `--`. A single argument is saved \
+                             verbatim, several are joined with shell quoting. Read from stdin \
+                             if omitted",
+                        )
+                        .num_args(1..)
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .short('C')
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("Directory to run the command in (default: the current one)"),
+                )
+                .args(save_options_args()),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search saved commands and pick one to execute")
+                .arg(Arg::new("query").value_name("QUERY").help(
+                    "What to search for; refine it in the picker, or type it there if omitted",
+                ))
+                .args(search_args())
+                .args(filter_args())
+                .arg(
+                    format_arg().help(
+                        "Print the results in this format instead of picking one interactively",
+                    ),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .visible_alias("ls")
+                .about("List saved commands")
+                .arg(sort_arg())
+                .args(filter_args())
+                .args(page_args())
+                .arg(format_arg()),
+        )
+        .subcommand(Command::new("tags").about("List all tags and groups in use"))
+        .subcommand(
+            Command::new("describe")
+                .about("Set the description of a saved command")
+                .arg(id_arg())
+                .arg(
+                    Arg::new("text")
+                        .value_name("TEXT")
+                        .help("New description, empty to remove it; prompts for it if omitted"),
+                ),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Print everything stored about a saved command")
+                .arg(id_arg())
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Edit a saved command, its description, directory and labels in $EDITOR")
+                .arg(id_arg()),
+        )
+        .subcommand(
+            Command::new("tag")
+                .about("Change the tags and group of a saved command")
+                .arg(id_arg())
+                .args(label_args()),
+        )
+        .subcommand(
+            Command::new("rm")
+                .visible_alias("delete")
+                .about("Delete a saved command")
+                .arg(id_arg()),
+        )
+        .subcommand(Command::new("clear").about("Delete all saved commands"))
+        .subcommand(Command::new("init").about("Print the shell integration"))
+        .subcommand(
+            Command::new("migrate")
+                .about("Import commands from the legacy JSON store (~/.komando.json)")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("Legacy store to import (default: ~/.komando.json)"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Report what would be imported without changing the database")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Check the database for inconsistencies left by interrupted writes")
+                .arg(
+                    Arg::new("repair")
+                        .long("repair")
+                        .help("Fix the problems that were found")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Show or change the settings in ~/.config/komando/config.toml")
+                .subcommand(
+                    Command::new("list")
+                        .about("Print every setting and where its value comes from"),
+                )
+                .subcommand(
+                    Command::new("get")
+                        .about("Print the value of a setting")
+                        .arg(setting_arg()),
+                )
+                .subcommand(
+                    Command::new("set")
+                        .about("Change a setting in the config file")
+                        .arg(setting_arg())
+                        .arg(
+                            Arg::new("value")
+                                .value_name("VALUE")
+                                .required(true)
+                                .allow_hyphen_values(true),
+                        ),
+                )
+                .subcommand(Command::new("path").about("Print the location of the config file")),
+        )
+        // Options of a bare `komando`, which saves the last command
+        .args(save_args())
+        .args(legacy_args())
+}
+
+/// Parses the process arguments, exiting with a usage message if they are invalid.
+pub fn parse() -> Result<Action> {
+    Action::from_matches(&command().get_matches())
+}
+
+impl Action {
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        let action = match matches.subcommand() {
+            Some(("save", m)) => Action::Save(SaveArgs::from_matches(m)?),
+            Some(("add", m)) => Action::Add(AddArgs::from_matches(m)?),
+            Some(("search", m)) => Action::Search(SearchArgs::from_matches(m)?),
+            Some(("list", m)) => Action::List(ListArgs::from_matches(m)?),
+            Some(("tags", _)) => Action::ListTags,
+            Some(("describe", m)) => Action::Describe {
+                id: string(m, "id").unwrap_or_default(),
+                description: string(m, "text"),
+            },
+            Some(("tag", m)) => Action::Label(LabelArgs::from_matches(m)),
+            Some(("edit", m)) => Action::Edit {
+                id: string(m, "id").unwrap_or_default(),
+            },
+            Some(("show", m)) => Action::Show {
+                id: string(m, "id").unwrap_or_default(),
+                format: parse_optional(m, "format")?,
+            },
+            Some(("rm", m)) => Action::Remove {
+                id: string(m, "id").unwrap_or_default(),
+            },
+            Some(("clear", _)) => Action::Clear,
+            Some(("init", _)) => Action::Init,
+            Some(("migrate", m)) => Action::Migrate {
+                file: string(m, "file").map(PathBuf::from),
+                dry_run: m.get_flag("dry-run"),
+            },
+            Some(("check", m)) => Action::Check {
+                repair: m.get_flag("repair"),
+            },
+            Some(("config", m)) => Action::Config(match m.subcommand() {
+                Some(("get", m)) => ConfigAction::Get {
+                    key: string(m, "key").unwrap_or_default(),
+                },
+                Some(("set", m)) => ConfigAction::Set {
+                    key: string(m, "key").unwrap_or_default(),
+                    value: string(m, "value").unwrap_or_default(),
+                },
+                Some(("path", _)) => ConfigAction::Path,
+                _ => ConfigAction::List,
+            }),
+            Some((name, _)) => unreachable!("unhandled subcommand '{}'", name),
+            None => Self::from_legacy_flags(matches)?,
+        };
+        Ok(action)
+    }
+
+    /// The command ID the action applies to, if any.
+    pub fn id_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Action::Describe { id, .. }
+            | Action::Edit { id }
+            | Action::Show { id, .. }
+            | Action::Remove { id }
+            | Action::Label(LabelArgs { id, .. }) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The filter of the commands the action lists or searches, if any.
+    pub fn filter_mut(&mut self) -> Option<&mut ListFilter> {
+        match self {
+            Action::Search(SearchArgs { filter, .. }) | Action::List(ListArgs { filter, .. }) => {
+                Some(filter)
+            }
+            _ => None,
+        }
+    }
+
+    /// Maps the flags of earlier releases, with the precedence they had then.
+    fn from_legacy_flags(m: &ArgMatches) -> Result<Self> {
+        let action = if m.get_flag("init") {
+            Action::Init
+        } else if m.get_flag("save") {
+            Action::Save(SaveArgs::from_matches(m)?)
+        } else if m.get_flag("list") {
+            Action::List(ListArgs::from_matches(m)?)
+        } else if m.get_flag("list-tags") {
+            Action::ListTags
+        } else if let Some(id) = string(m, "update") {
+            Action::Label(LabelArgs {
+                id,
+                ..LabelArgs::from_matches(m)
+            })
+        } else if let Some(id) = string(m, "describe") {
+            // `--describe ID --description` without text used to prompt, like omitting it
+            Action::Describe {
+                id,
+                description: string(m, "description").filter(|d| !d.is_empty()),
+            }
+        } else if m.get_flag("clear") {
+            Action::Clear
+        } else if let Some(id) = string(m, "delete") {
+            Action::Remove { id }
+        } else if let Some(query) = string(m, "legacy-query") {
+            Action::Search(SearchArgs {
+                query,
+                ..SearchArgs::from_matches(m)?
+            })
+        } else {
+            Action::Save(SaveArgs::from_matches(m)?)
+        };
+        Ok(action)
+    }
+}
+
+impl SaveArgs {
+    fn from_matches(m: &ArgMatches) -> Result<Self> {
+        Ok(Self {
+            count: m.get_one::<u64>("count").map(|&count| count as usize),
+            height: height(m),
+            options: SaveOptions::from_matches(m)?,
+        })
+    }
+}
+
+impl AddArgs {
+    fn from_matches(m: &ArgMatches) -> Result<Self> {
+        let words = strings(m, "command");
+        Ok(Self {
+            command: match words.as_slice() {
+                [] => None,
+                [command] => Some(command.clone()),
+                words => Some(shell_join(words)),
+            },
+            directory: string(m, "dir").map(PathBuf::from),
+            options: SaveOptions::from_matches(m)?,
+        })
+    }
+}
+
+impl SaveOptions {
+    fn from_matches(m: &ArgMatches) -> Result<Self> {
+        Ok(Self {
+            description: string(m, "description"),
+            tags: strings(m, "tag"),
+            group: string(m, "group"),
+            dedup: parse_optional(m, "dedup")?,
+        })
+    }
+}
+
+impl SearchArgs {
+    fn from_matches(m: &ArgMatches) -> Result<Self> {
+        Ok(Self {
+            query: m
+                .try_get_one::<String>("query")
+                .ok()
+                .flatten()
+                .cloned()
+                .unwrap_or_default(),
+            mode: parse_optional(m, "mode")?,
+            filter: filter(m),
+            show_scores: m.get_flag("scores"),
+            height: height(m),
+            format: parse_optional(m, "format")?,
+        })
+    }
+}
+
+impl ListArgs {
+    fn from_matches(m: &ArgMatches) -> Result<Self> {
+        Ok(Self {
+            filter: filter(m),
+            sort: parse_optional(m, "sort")?,
+            page: Page {
+                limit: number(m, "limit"),
+                offset: number(m, "offset").unwrap_or(0),
+            },
+            format: parse_optional(m, "format")?,
+        })
+    }
+}
+
+impl LabelArgs {
+    fn from_matches(m: &ArgMatches) -> Self {
+        Self {
+            id: m
+                .try_get_one::<String>("id")
+                .ok()
+                .flatten()
+                .cloned()
+                .unwrap_or_default(),
+            tags: strings(m, "tag"),
+            untags: strings(m, "untag"),
+            group: string(m, "group"),
+            ungroup: m.get_flag("ungroup"),
+        }
+    }
+}
+
+/// The value of an argument, or `None` if it was not given or does not exist in `m`
+/// (the legacy flags lack the newer options of the subcommands).
+fn string(m: &ArgMatches, id: &str) -> Option<String> {
+    m.try_get_one::<String>(id).ok().flatten().cloned()
+}
+
+fn number(m: &ArgMatches, id: &str) -> Option<usize> {
+    m.try_get_one::<u64>(id)
+        .ok()
+        .flatten()
+        .map(|&value| value as usize)
+}
+
+fn height(m: &ArgMatches) -> Option<Height> {
+    m.try_get_one::<Height>("height").ok().flatten().copied()
+}
+
+fn strings(m: &ArgMatches, id: &str) -> Vec<String> {
+    m.get_many::<String>(id)
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
+fn filter(m: &ArgMatches) -> ListFilter {
+    ListFilter {
+        tags: strings(m, "tag"),
+        group: string(m, "group"),
+        directory: string(m, "dir"),
+        under: string(m, "under"),
+        since: string(m, "since"),
+        until: string(m, "until"),
+        program: string(m, "program"),
+    }
+}
+
+/// Parses the time of `--since` or `--until` into an RFC 3339 timestamp. Accepts a
+/// timestamp, a local date such as `2024-05-01`, or a duration before now in hours,
+/// days or weeks (`12h`, `3d`, `2w`). With `end_of_day`, a date means the end of that
+/// day rather than its start.
+fn parse_time(value: &str, end_of_day: bool) -> Result<String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.to_rfc3339());
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = if end_of_day {
+            date.succ_opt().context("Date out of range")?
+        } else {
+            date
+        };
+        let midnight = Local
+            .from_local_datetime(&date.and_time(Default::default()))
+            .earliest()
+            .with_context(|| format!("'{}' has no midnight in the local time zone", value))?;
+        return Ok(midnight.with_timezone(&Utc).to_rfc3339());
+    }
+
+    let split = value.len().saturating_sub(1);
+    if let (Some(Ok(count)), Some(unit)) = (
+        value.get(..split).map(str::parse::<u32>),
+        value.get(split..),
+    ) {
+        let duration = match unit {
+            "h" => Some(Duration::hours(count.into())),
+            "d" => Some(Duration::days(count.into())),
+            "w" => Some(Duration::weeks(count.into())),
+            _ => None,
+        };
+        if let Some(duration) = duration {
+            return Ok((Utc::now() - duration).to_rfc3339());
+        }
+    }
+
+    bail!(
+        "Invalid time '{}', expected a date (2024-05-01), an RFC 3339 timestamp \
+         or a duration such as 12h, 3d or 2w",
+        value
+    )
+}
+
+fn parse_optional<T>(m: &ArgMatches, id: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr<Err = anyhow::Error>,
+{
+    m.get_one::<String>(id).map(|s| s.parse()).transpose()
+}
+
+fn id_arg() -> Arg {
+    Arg::new("id")
+        .value_name("ID")
+        .help("ID of the saved command, or a unique prefix of it")
+        .required(true)
+}
+
+fn tag_arg() -> Arg {
+    Arg::new("tag")
+        .short('t')
+        .long("tag")
+        .value_name("TAG")
+        .action(ArgAction::Append)
+}
+
+fn group_arg() -> Arg {
+    Arg::new("group")
+        .short('g')
+        .long("group")
+        .value_name("GROUP")
+        .num_args(1)
+}
+
+/// Joins words into a command line, quoting those the shell would otherwise split or expand.
+fn shell_join(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| {
+            let is_plain = !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "-_./:=,+@%".contains(c));
+            if is_plain {
+                word.clone()
+            } else {
+                format!("'{}'", word.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn save_args() -> Vec<Arg> {
+    let mut args = vec![Arg::new("count")
+        .short('n')
+        .long("number")
+        .value_name("COUNT")
+        .help("Pick the commands to save among the COUNT most recent ones")
+        .value_parser(clap::value_parser!(u64).range(1..))];
+    args.push(height_arg());
+    args.extend(save_options_args());
+    args
+}
+
+fn save_options_args() -> Vec<Arg> {
+    vec![
+        Arg::new("description")
+            .short('m')
+            .long("description")
+            .value_name("TEXT")
+            .help("Description to attach; prompts for it if no TEXT is given")
+            .num_args(0..=1)
+            .default_missing_value(""),
+        tag_arg().help("Tag to assign"),
+        group_arg().help("Group to assign"),
+        Arg::new("dedup")
+            .long("dedup")
+            .value_name("POLICY")
+            .help("What counts as a duplicate of an already saved command [default: save.dedup]")
+            .value_parser(DedupPolicy::NAMES),
+    ]
+}
+
+fn search_args() -> Vec<Arg> {
+    vec![
+        Arg::new("mode")
+            .long("mode")
+            .value_name("MODE")
+            .help(if cfg!(feature = "embeddings") {
+                "How commands are matched: full-text words, fuzzy abbreviations, meaning, \
+                 or words and meaning combined [default: search.mode]"
+            } else {
+                "How commands are matched: full-text words or fuzzy abbreviations \
+                 [default: search.mode]"
+            })
+            .value_parser(SearchMode::NAMES),
+        Arg::new("scores")
+            .long("scores")
+            .help("Show the relevance score (and vector distance) of each result")
+            .action(ArgAction::SetTrue),
+        height_arg(),
+    ]
+}
+
+fn height_arg() -> Arg {
+    Arg::new("height")
+        .long("height")
+        .value_name("HEIGHT")
+        .help(
+            "Show the picker in HEIGHT rows (or HEIGHT% of the screen) below the prompt \
+             instead of full screen [default: picker.height]",
+        )
+        .value_parser(|value: &str| value.parse::<Height>())
+}
+
+fn filter_args() -> Vec<Arg> {
+    vec![
+        tag_arg().help("Only commands with this tag (repeat to require several)"),
+        group_arg().help("Only commands in this group"),
+        Arg::new("dir")
+            .long("dir")
+            .value_name("DIR")
+            .help("Only commands saved in this directory"),
+        Arg::new("under")
+            .long("under")
+            .value_name("DIR")
+            .help("Only commands saved in this directory or below it")
+            .conflicts_with("dir"),
+        Arg::new("since")
+            .long("since")
+            .value_name("WHEN")
+            .help("Only commands saved since WHEN: a date (2024-05-01), a timestamp, or 12h, 3d, 2w ago")
+            .value_parser(|value: &str| parse_time(value, false)),
+        Arg::new("until")
+            .long("until")
+            .value_name("WHEN")
+            .help("Only commands saved before WHEN, or on that day if it is a date")
+            .value_parser(|value: &str| parse_time(value, true)),
+        Arg::new("program")
+            .short('p')
+            .long("program")
+            .value_name("NAME")
+            .help("Only commands running this program, i.e. whose first word is NAME"),
+    ]
+}
+
+fn page_args() -> Vec<Arg> {
+    vec![
+        Arg::new("limit")
+            .long("limit")
+            .value_name("N")
+            .help("Show at most N commands")
+            .value_parser(clap::value_parser!(u64)),
+        Arg::new("offset")
+            .long("offset")
+            .value_name("N")
+            .help("Skip the first N commands")
+            .value_parser(clap::value_parser!(u64)),
+    ]
+}
+
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .value_name("FORMAT")
+        .help("Print machine-readable output in this format")
+        .value_parser(Format::NAMES)
+}
+
+fn sort_arg() -> Arg {
+    Arg::new("sort")
+        .long("sort")
+        .value_name("KEY")
+        .help("Sort order [default: list.sort]")
+        .value_parser(SortOrder::NAMES)
+}
+
+fn setting_arg() -> Arg {
+    Arg::new("key")
+        .value_name("KEY")
+        .help("Name of the setting")
+        .required(true)
+        .value_parser(PossibleValuesParser::new(
+            SETTINGS
+                .iter()
+                .map(|setting| PossibleValue::new(setting.key).help(setting.help)),
+        ))
+}
+
+fn label_args() -> Vec<Arg> {
+    vec![
+        tag_arg().help("Tag to add"),
+        Arg::new("untag")
+            .long("untag")
+            .value_name("TAG")
+            .help("Tag to remove")
+            .action(ArgAction::Append),
+        group_arg().help("Group to move the command to"),
+        Arg::new("ungroup")
+            .long("ungroup")
+            .help("Remove the command from its group")
+            .conflicts_with("group")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// Top-level flags of earlier releases, hidden from the help.
+fn legacy_args() -> Vec<Arg> {
+    let flag = |id: &'static str| Arg::new(id).long(id).hide(true).action(ArgAction::SetTrue);
+    let value = |id: &'static str, name: &'static str| {
+        Arg::new(id)
+            .long(id)
+            .value_name(name)
+            .hide(true)
+            .num_args(1)
+    };
+
+    vec![
+        flag("save").short('s'),
+        Arg::new("legacy-query")
+            .short('q')
+            .long("query")
+            .value_name("QUERY")
+            .hide(true)
+            .num_args(0..=1)
+            .default_missing_value(""),
+        flag("list").short('l'),
+        value("delete", "ID").short('d'),
+        flag("clear"),
+        flag("init"),
+        flag("list-tags"),
+        value("describe", "ID"),
+        value("update", "ID"),
+        Arg::new("untag")
+            .long("untag")
+            .value_name("TAG")
+            .hide(true)
+            .requires("update")
+            .action(ArgAction::Append),
+        flag("ungroup").requires("update").conflicts_with("group"),
+        sort_arg().hide(true),
+        format_arg().hide(true),
+        search_args()[0].clone().hide(true),
+        search_args()[1].clone().hide(true),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_from(args: &[&str]) -> Action {
+        let matches = command()
+            .try_get_matches_from(std::iter::once("komando").chain(args.iter().copied()))
+            .unwrap();
+        Action::from_matches(&matches).unwrap()
+    }
+
+    fn saved(tags: &[&str], description: Option<&str>) -> SaveArgs {
+        SaveArgs {
+            count: None,
+            height: None,
+            options: SaveOptions {
+                description: description.map(str::to_string),
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                group: None,
+                dedup: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_command_is_valid() {
+        command().debug_assert();
+    }
+
+    #[test]
+    fn test_bare_invocation_saves() {
+        assert_eq!(parse_from(&[]), Action::Save(saved(&[], None)));
+        assert_eq!(
+            parse_from(&["-t", "k8s", "-m"]),
+            Action::Save(saved(&["k8s"], Some("")))
+        );
+        assert_eq!(
+            parse_from(&["-n", "10"]),
+            Action::Save(SaveArgs {
+                count: Some(10),
+                ..saved(&[], None)
+            })
+        );
+    }
+
+    #[test]
+    fn test_legacy_flags_match_subcommands() {
+        let pairs: &[(&[&str], &[&str])] = &[
+            (&["-s", "-t", "a"], &["save", "-t", "a"]),
+            (
+                &["-q", "push", "--mode", "fuzzy", "-g", "proj"],
+                &["search", "push", "--mode", "fuzzy", "-g", "proj"],
+            ),
+            (&["-q"], &["search"]),
+            (&["-l", "--sort", "uses"], &["list", "--sort", "uses"]),
+            (
+                &["-q", "push", "--format", "jsonl"],
+                &["search", "push", "--format", "jsonl"],
+            ),
+            (&["--list-tags"], &["tags"]),
+            (
+                &["--describe", "id1", "-m", "text"],
+                &["describe", "id1", "text"],
+            ),
+            (&["--describe", "id1", "-m"], &["describe", "id1"]),
+            (
+                &["--update", "id1", "-t", "a", "--untag", "b", "--ungroup"],
+                &["tag", "id1", "-t", "a", "--untag", "b", "--ungroup"],
+            ),
+            (&["-d", "id1"], &["rm", "id1"]),
+            (&["--clear"], &["clear"]),
+            (&["--init"], &["init"]),
+        ];
+
+        for (legacy, subcommand) in pairs {
+            assert_eq!(parse_from(legacy), parse_from(subcommand), "{:?}", legacy);
+        }
+    }
+
+    #[test]
+    fn test_subcommand_arguments() {
+        assert_eq!(
+            parse_from(&["search", "docker", "-t", "a", "-t", "b", "--scores", "--height", "40%",]),
+            Action::Search(SearchArgs {
+                query: "docker".to_string(),
+                mode: None,
+                filter: ListFilter {
+                    tags: vec!["a".to_string(), "b".to_string()],
+                    ..Default::default()
+                },
+                show_scores: true,
+                height: Some(Height::Percent(40)),
+                format: None,
+            })
+        );
+        assert_eq!(
+            parse_from(&["save", "-n", "5", "--height", "12"]),
+            Action::Save(SaveArgs {
+                count: Some(5),
+                height: Some(Height::Lines(12)),
+                ..saved(&[], None)
+            })
+        );
+        assert!(command()
+            .try_get_matches_from(["komando", "search", "--height", "0"])
+            .is_err());
+        assert_eq!(
+            parse_from(&["migrate", "--dry-run", "old.json"]),
+            Action::Migrate {
+                file: Some(PathBuf::from("old.json")),
+                dry_run: true,
+            }
+        );
+        assert_eq!(
+            parse_from(&["ls", "--sort", "last-used"]),
+            Action::List(ListArgs {
+                filter: ListFilter::default(),
+                sort: Some(SortOrder::LastUsed),
+                page: Page::default(),
+                format: None,
+            })
+        );
+        assert_eq!(
+            parse_from(&[
+                "list",
+                "--under",
+                "/srv",
+                "-p",
+                "git",
+                "--since",
+                "2024-05-01T10:00:00Z",
+                "--sort",
+                "alpha",
+                "--limit",
+                "20",
+                "--offset",
+                "40",
+            ]),
+            Action::List(ListArgs {
+                filter: ListFilter {
+                    under: Some("/srv".to_string()),
+                    program: Some("git".to_string()),
+                    since: Some("2024-05-01T10:00:00+00:00".to_string()),
+                    ..Default::default()
+                },
+                sort: Some(SortOrder::Alphabetical),
+                page: Page {
+                    limit: Some(20),
+                    offset: 40,
+                },
+                format: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_combinations() {
+        let parse = |args: &[&str]| {
+            command().try_get_matches_from(std::iter::once("komando").chain(args.iter().copied()))
+        };
+
+        assert!(parse(&["list", "--dedup", "global"]).is_err());
+        assert!(parse(&["-s", "list"]).is_err());
+        assert!(parse(&["--untag", "a"]).is_err());
+        assert!(parse(&["tag", "id1", "-g", "a", "--ungroup"]).is_err());
+        assert!(parse(&["list", "--dir", "a", "--under", "b"]).is_err());
+        assert!(parse(&["search", "x", "--limit", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        assert_eq!(parse_from(&["config"]), Action::Config(ConfigAction::List));
+        assert_eq!(
+            parse_from(&["config", "set", "search.limit", "20"]),
+            Action::Config(ConfigAction::Set {
+                key: "search.limit".to_string(),
+                value: "20".to_string(),
+            })
+        );
+        assert!(command()
+            .try_get_matches_from(["komando", "config", "get", "search.limmit"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_add() {
+        let Action::Add(args) = parse_from(&["add", "-C", "/srv", "-t", "ops", "--", "ls", "-la"])
+        else {
+            panic!("expected add");
+        };
+        assert_eq!(args.command.as_deref(), Some("ls -la"));
+        assert_eq!(args.directory, Some(PathBuf::from("/srv")));
+        assert_eq!(args.options.tags, vec!["ops"]);
+
+        let Action::Add(args) = parse_from(&["add", "--", "awk '{print $1}' | sort"]) else {
+            panic!("expected add");
+        };
+        assert_eq!(args.command.as_deref(), Some("awk '{print $1}' | sort"));
+
+        let Action::Add(args) = parse_from(&["add"]) else {
+            panic!("expected add");
+        };
+        assert_eq!(args.command, None);
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let since = |value| parse_time(value, false).unwrap();
+        let until = |value| parse_time(value, true).unwrap();
+        let time = |value: &str| DateTime::parse_from_rfc3339(value).unwrap();
+
+        assert_eq!(
+            since("2024-05-01T10:00:00+02:00"),
+            "2024-05-01T10:00:00+02:00"
+        );
+        assert_eq!(
+            time(&until("2024-05-01")) - time(&since("2024-05-01")),
+            Duration::days(1)
+        );
+        let three_days_ago = time(&since("3d"));
+        let ago = Utc::now().signed_duration_since(three_days_ago);
+        assert!(ago >= Duration::days(3) && ago < Duration::days(3) + Duration::minutes(1));
+
+        assert!(parse_time("yesterday", false).is_err());
+        assert!(parse_time("-3d", false).is_err());
+        assert!(parse_time("3é", false).is_err());
+    }
+
+    #[test]
+    fn test_shell_join() {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            shell_join(&words(&["git", "commit", "-m", "fix it"])),
+            "git commit -m 'fix it'"
+        );
+        assert_eq!(
+            shell_join(&words(&["echo", "it's", "$HOME", ""])),
+            r"echo 'it'\''s' '$HOME' ''"
+        );
+        assert_eq!(
+            shell_join(&words(&["curl", "https://example.com/a?b=1"])),
+            "curl 'https://example.com/a?b=1'"
+        );
+    }
+}
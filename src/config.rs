@@ -0,0 +1,591 @@
+//! User configuration.
+//!
+//! Settings are read from `~/.config/komando/config.toml` (or the file named by
+//! `KOMANDO_CONFIG`), and each can be overridden by an environment variable named after
+//! its key: `search.limit` is overridden by `KOMANDO_SEARCH_LIMIT`. Missing settings
+//! take their documented default, which for keys depends on `keys.preset`.
+//!
+//! ```toml
+//! [search]
+//! mode = "fuzzy"
+//! limit = 20
+//!
+//! [keys]
+//! up = "up,ctrl-p"
+//! ```
+
+use anyhow::{bail, Context, Result};
+use dirs::home_dir;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::db::{DedupPolicy, SearchMode, SortOrder};
+use crate::keymap::{self, Keymap, PickerAction, Preset};
+use crate::picker::{Height, Preview};
+
+/// A configurable setting.
+pub struct Setting {
+    /// Dotted key: the table and the name of the setting in the config file
+    pub key: &'static str,
+    pub default: &'static str,
+    pub help: &'static str,
+    /// Written as a TOML integer rather than a string
+    integer: bool,
+}
+
+impl Setting {
+    const fn text(key: &'static str, default: &'static str, help: &'static str) -> Self {
+        Self {
+            key,
+            default,
+            help,
+            integer: false,
+        }
+    }
+
+    /// The environment variable overriding this setting.
+    pub fn env_var(&self) -> String {
+        format!("KOMANDO_{}", self.key.replace('.', "_").to_uppercase())
+    }
+}
+
+/// Every setting, in the order they are listed.
+pub const SETTINGS: [Setting; 26] = [
+    Setting::text(
+        "database.path",
+        "~/.komando.db",
+        "Location of the command database",
+    ),
+    Setting::text(
+        "search.mode",
+        if cfg!(feature = "embeddings") {
+            "hybrid"
+        } else {
+            "text"
+        },
+        "Default search mode",
+    ),
+    Setting {
+        integer: true,
+        ..Setting::text("search.limit", "10", "Number of search results shown")
+    },
+    Setting::text(
+        "search.preview",
+        "hidden",
+        "Where the preview of the selected result is shown",
+    ),
+    Setting::text(
+        "save.dedup",
+        "per-directory",
+        "What counts as a duplicate of an already saved command",
+    ),
+    Setting::text(
+        "list.sort",
+        "created",
+        "Default sort order of 'komando list'",
+    ),
+    Setting::text(
+        "picker.height",
+        "full",
+        "Rows taken by the picker below the prompt ('full' for the whole screen)",
+    ),
+    Setting::text(
+        "picker.mouse",
+        "true",
+        "Whether clicks select picker entries and the wheel scrolls",
+    ),
+    Setting::text(
+        "keys.preset",
+        "default",
+        "Default keys of the picker: default, emacs or vim",
+    ),
+    Setting::text("keys.up", "up", "Keys moving the picker selection up"),
+    Setting::text("keys.down", "down", "Keys moving the picker selection down"),
+    Setting::text(
+        "keys.page_up",
+        "pageup",
+        "Keys moving the picker selection up by a screen",
+    ),
+    Setting::text(
+        "keys.page_down",
+        "pagedown",
+        "Keys moving the picker selection down by a screen",
+    ),
+    Setting::text(
+        "keys.first",
+        "home",
+        "Keys selecting the first picker entry",
+    ),
+    Setting::text("keys.last", "end", "Keys selecting the last picker entry"),
+    Setting::text(
+        "keys.mark",
+        "space,tab",
+        "Keys marking an entry when several can be picked",
+    ),
+    Setting::text(
+        "keys.search",
+        "/",
+        "Keys focusing the query in the vim preset",
+    ),
+    Setting::text(
+        "keys.preview",
+        "ctrl-o",
+        "Keys showing or hiding the preview",
+    ),
+    Setting::text(
+        "keys.delete",
+        "delete",
+        "Keys deleting the selected search result",
+    ),
+    Setting::text("keys.edit", "ctrl-e", "Keys editing the selected command"),
+    Setting::text(
+        "keys.tag",
+        "ctrl-t",
+        "Keys editing the tags of the selected command",
+    ),
+    Setting::text(
+        "keys.describe",
+        "alt-d",
+        "Keys editing the description of the selected command",
+    ),
+    Setting::text(
+        "keys.copy",
+        "ctrl-y",
+        "Keys copying the selected command to the clipboard",
+    ),
+    Setting::text("keys.accept", "enter", "Keys picking the selection"),
+    Setting::text(
+        "keys.print",
+        "alt-enter",
+        "Keys picking the selected command to print rather than execute it",
+    ),
+    Setting::text(
+        "keys.cancel",
+        "esc,ctrl-d",
+        "Keys closing the picker (Ctrl-C always does)",
+    ),
+];
+
+/// Where the value of a setting comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    /// The default of the key preset of that name
+    Preset(String),
+    File,
+    /// The environment variable of that name
+    Env(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Preset(name) => write!(f, "{} preset", name),
+            Source::File => write!(f, "config file"),
+            Source::Env(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// The settings in effect.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub db_path: PathBuf,
+    pub search_mode: SearchMode,
+    pub search_limit: usize,
+    pub preview: Preview,
+    pub height: Height,
+    pub dedup: DedupPolicy,
+    pub sort: SortOrder,
+    pub keymap: Keymap,
+}
+
+impl Config {
+    /// Loads the config file and applies the environment overrides.
+    pub fn load() -> Result<Self> {
+        ConfigFile::open()?.config()
+    }
+
+    /// Builds the configuration from a lookup of the value of each setting.
+    fn from_values(value: impl Fn(&Setting) -> Result<(String, Source)>) -> Result<Self> {
+        let mut config = Config {
+            db_path: PathBuf::new(),
+            search_mode: SearchMode::default(),
+            search_limit: 0,
+            preview: Preview::default(),
+            height: Height::default(),
+            dedup: DedupPolicy::default(),
+            sort: SortOrder::default(),
+            keymap: Keymap::default(),
+        };
+        for setting in &SETTINGS {
+            let (value, source) = value(setting)?;
+            config
+                .apply(setting.key, &value)
+                .with_context(|| format!("Invalid {} '{}' ({})", setting.key, value, source))?;
+        }
+        Ok(config)
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<()> {
+        let action = match key {
+            "keys.up" => Some(PickerAction::Up),
+            "keys.down" => Some(PickerAction::Down),
+            "keys.page_up" => Some(PickerAction::PageUp),
+            "keys.page_down" => Some(PickerAction::PageDown),
+            "keys.first" => Some(PickerAction::First),
+            "keys.last" => Some(PickerAction::Last),
+            "keys.mark" => Some(PickerAction::Mark),
+            "keys.search" => Some(PickerAction::Search),
+            "keys.preview" => Some(PickerAction::Preview),
+            "keys.delete" => Some(PickerAction::Delete),
+            "keys.edit" => Some(PickerAction::Edit),
+            "keys.tag" => Some(PickerAction::Tag),
+            "keys.describe" => Some(PickerAction::Describe),
+            "keys.copy" => Some(PickerAction::Copy),
+            "keys.print" => Some(PickerAction::Print),
+            "keys.accept" => Some(PickerAction::Accept),
+            "keys.cancel" => Some(PickerAction::Cancel),
+            _ => None,
+        };
+        if let Some(action) = action {
+            self.keymap.bind(action, &keymap::parse_keys(value)?);
+            return Ok(());
+        }
+
+        match key {
+            "database.path" => self.db_path = expand_home(value)?,
+            "search.mode" => self.search_mode = value.parse()?,
+            "search.limit" => {
+                self.search_limit = value
+                    .parse()
+                    .ok()
+                    .filter(|&limit| limit > 0)
+                    .context("Expected a positive number")?
+            }
+            "search.preview" => self.preview = value.parse()?,
+            "picker.height" => self.height = value.parse()?,
+            "picker.mouse" => {
+                self.keymap.mouse = value.parse().ok().context("Expected true or false")?
+            }
+            "keys.preset" => value.parse::<Preset>()?.apply(&mut self.keymap),
+            "save.dedup" => self.dedup = value.parse()?,
+            "list.sort" => self.sort = value.parse()?,
+            _ => unreachable!("unhandled setting '{}'", key),
+        }
+        Ok(())
+    }
+}
+
+/// The config file, as it is on disk. Edits keep its comments and layout.
+pub struct ConfigFile {
+    path: PathBuf,
+    document: DocumentMut,
+}
+
+impl ConfigFile {
+    /// Opens the config file; a missing file is the same as an empty one.
+    pub fn open() -> Result<Self> {
+        let path = match env::var_os("KOMANDO_CONFIG") {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => home_dir()
+                .context("Could not determine home directory")?
+                .join(".config")
+                .join("komando")
+                .join("config.toml"),
+        };
+        Self::open_path(path)
+    }
+
+    fn open_path(path: PathBuf) -> Result<Self> {
+        let document = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .parse()
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DocumentMut::new(),
+            Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+        };
+        let file = Self { path, document };
+
+        for key in file.unknown_keys() {
+            eprintln!(
+                ">>> Warning: ignoring unknown setting '{}' in {}",
+                key,
+                file.path.display()
+            );
+        }
+        Ok(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The settings in effect: the environment, then this file, then the defaults.
+    pub fn config(&self) -> Result<Config> {
+        Config::from_values(|setting| self.value(setting))
+    }
+
+    /// The value in effect for a setting, and where it comes from.
+    pub fn value(&self, setting: &Setting) -> Result<(String, Source)> {
+        let env_var = setting.env_var();
+        if let Some(value) = env::var(&env_var).ok().filter(|v| !v.is_empty()) {
+            return Ok((value, Source::Env(env_var)));
+        }
+
+        let (table, name) = split_key(setting.key);
+        let item = self.document.get(table).and_then(|table| table.get(name));
+        match item.and_then(Item::as_value) {
+            Some(Value::String(value)) => Ok((value.value().clone(), Source::File)),
+            Some(Value::Integer(value)) => Ok((value.value().to_string(), Source::File)),
+            Some(Value::Boolean(value)) => Ok((value.value().to_string(), Source::File)),
+            Some(_) => bail!(
+                "{} in {} must be a string, an integer or a boolean",
+                setting.key,
+                self.path.display()
+            ),
+            None if item.is_some() => bail!(
+                "{} in {} must be a value, not a table",
+                setting.key,
+                self.path.display()
+            ),
+            None => Ok(self.default(setting)),
+        }
+    }
+
+    /// The default of a setting, taking the key preset into account.
+    fn default(&self, setting: &Setting) -> (String, Source) {
+        let preset_keys = setting
+            .key
+            .strip_prefix("keys.")
+            .filter(|&name| name != "preset")
+            .and_then(|name| {
+                let (preset, _) = self.value(self::setting("keys.preset")?).ok()?;
+                // An invalid preset is reported with the preset itself
+                let keys = preset.parse::<Preset>().ok()?.keys(name)?;
+                Some((keys, preset))
+            });
+        match preset_keys {
+            Some((keys, preset)) => (keys.to_string(), Source::Preset(preset)),
+            None => (setting.default.to_string(), Source::Default),
+        }
+    }
+
+    /// Sets a setting in the file, after checking that the value is valid.
+    pub fn set(&mut self, setting: &Setting, value: &str) -> Result<()> {
+        let mut config = Config::from_values(|s| Ok((s.default.to_string(), Source::Default)))?;
+        config
+            .apply(setting.key, value)
+            .with_context(|| format!("Invalid {} '{}'", setting.key, value))?;
+
+        let (table, name) = split_key(setting.key);
+        if !self.document.contains_table(table) {
+            self.document[table] = toml_edit::table();
+        }
+        self.document[table][name] = match value.parse::<i64>() {
+            Ok(number) if setting.integer => toml_edit::value(number),
+            _ => toml_edit::value(value),
+        };
+        Ok(())
+    }
+
+    /// Writes the file, creating its directory if needed.
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, self.document.to_string())
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Keys present in the file that are not settings, likely typos.
+    fn unknown_keys(&self) -> Vec<String> {
+        let mut unknown = Vec::new();
+        for (table, item) in self.document.iter() {
+            match item.as_table_like() {
+                Some(entries) => {
+                    for (name, _) in entries.iter() {
+                        let key = format!("{}.{}", table, name);
+                        if setting(&key).is_none() {
+                            unknown.push(key);
+                        }
+                    }
+                }
+                None => unknown.push(table.to_string()),
+            }
+        }
+        unknown
+    }
+}
+
+/// Looks up a setting by its key.
+pub fn setting(key: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.key == key)
+}
+
+fn split_key(key: &str) -> (&str, &str) {
+    key.split_once('.')
+        .expect("setting keys have a table and a name")
+}
+
+/// Expands a leading `~` to the home directory.
+fn expand_home(path: &str) -> Result<PathBuf> {
+    if path.is_empty() {
+        bail!("Expected a path");
+    }
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = home_dir().context("Could not determine home directory")?;
+            Ok(home.join(rest.trim_start_matches('/')))
+        }
+        _ => Ok(PathBuf::from(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use tempfile::NamedTempFile;
+
+    fn open(contents: &str) -> (NamedTempFile, ConfigFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), contents).unwrap();
+        let file = ConfigFile::open_path(temp_file.path().to_path_buf()).unwrap();
+        (temp_file, file)
+    }
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let (_temp_file, file) = open("");
+        let config = file.config().unwrap();
+
+        assert_eq!(config.search_limit, 10);
+        assert_eq!(config.search_mode, SearchMode::default());
+        assert_eq!(config.dedup, DedupPolicy::default());
+        assert_eq!(config.sort, SortOrder::default());
+        assert_eq!(config.db_path, home_dir().unwrap().join(".komando.db"));
+        assert_eq!(
+            config
+                .keymap
+                .action(&KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE)),
+            Some(PickerAction::Mark)
+        );
+    }
+
+    #[test]
+    fn test_file_values() {
+        let (_temp_file, file) =
+            open("[search]\nmode = \"fuzzy\"\nlimit = 25\n\n[keys]\ndown = \"down,ctrl-n\"\n");
+        let config = file.config().unwrap();
+
+        assert_eq!(config.search_mode, SearchMode::Fuzzy);
+        assert_eq!(config.search_limit, 25);
+        assert_eq!(
+            file.value(setting("search.limit").unwrap()).unwrap(),
+            ("25".to_string(), Source::File)
+        );
+        assert_eq!(
+            config
+                .keymap
+                .action(&KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL)),
+            Some(PickerAction::Down)
+        );
+    }
+
+    #[test]
+    fn test_key_presets() {
+        let (_temp_file, file) =
+            open("[picker]\nmouse = false\n\n[keys]\npreset = \"vim\"\nlast = \"end\"\n");
+        let config = file.config().unwrap();
+
+        assert!(config.keymap.modal);
+        assert!(!config.keymap.mouse);
+        assert_eq!(
+            file.value(setting("keys.up").unwrap()).unwrap(),
+            ("up,k,ctrl-p".to_string(), Source::Preset("vim".to_string()))
+        );
+        // Keys set in the file win over the preset
+        assert_eq!(
+            file.value(setting("keys.last").unwrap()).unwrap(),
+            ("end".to_string(), Source::File)
+        );
+        assert_eq!(
+            config
+                .keymap
+                .action(&KeyEvent::new(KeyCode::Char('3'), KeyModifiers::NONE)),
+            Some(PickerAction::Jump(3))
+        );
+
+        let (_temp_file, file) = open("[keys]\npreset = \"helix\"\n");
+        assert!(file.config().is_err());
+
+        // Every preset keeps Ctrl-D for closing the picker
+        let ctrl_d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        for preset in Preset::NAMES {
+            let (_temp_file, file) = open(&format!("[keys]\npreset = \"{}\"\n", preset));
+            let keymap = file.config().unwrap().keymap;
+            assert_eq!(
+                keymap.action(&ctrl_d),
+                Some(PickerAction::Cancel),
+                "{}",
+                preset
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let (_temp_file, file) = open("[search]\nlimit = 0\n");
+        let err = file.config().unwrap_err();
+        assert!(format!("{:#}", err).contains("search.limit"));
+
+        let (_temp_file, file) = open("[list]\nsort = true\n");
+        assert!(file.config().is_err());
+
+        let (_temp_file, mut file) = open("");
+        assert!(file
+            .set(setting("save.dedup").unwrap(), "sometimes")
+            .is_err());
+        assert!(file.set(setting("keys.accept").unwrap(), "ctrl-").is_err());
+    }
+
+    #[test]
+    fn test_set_keeps_comments() {
+        let (temp_file, mut file) = open("# My settings\n[search]\nmode = \"text\" # fast\n");
+        file.set(setting("search.limit").unwrap(), "30").unwrap();
+        file.set(setting("list.sort").unwrap(), "uses").unwrap();
+        file.save().unwrap();
+
+        let text = fs::read_to_string(temp_file.path()).unwrap();
+        assert!(text.starts_with("# My settings\n"));
+        assert!(text.contains("mode = \"text\" # fast"));
+        assert!(text.contains("limit = 30"));
+        assert!(text.contains("[list]\nsort = \"uses\""));
+
+        let file = ConfigFile::open_path(temp_file.path().to_path_buf()).unwrap();
+        assert_eq!(file.config().unwrap().sort, SortOrder::UseCount);
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let (_temp_file, file) = open("[search]\nlimt = 3\n[ui]\ntheme = \"dark\"\n");
+        assert_eq!(file.unknown_keys(), ["search.limt", "ui.theme"]);
+    }
+
+    #[test]
+    fn test_env_var_names() {
+        assert_eq!(
+            setting("search.limit").unwrap().env_var(),
+            "KOMANDO_SEARCH_LIMIT"
+        );
+        assert_eq!(
+            setting("database.path").unwrap().env_var(),
+            "KOMANDO_DATABASE_PATH"
+        );
+    }
+}
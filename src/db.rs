@@ -1,7 +1,14 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+#[cfg(feature = "embeddings")]
+use std::collections::HashMap;
 use std::path::Path;
+use std::str::FromStr;
+
+use crate::fuzzy;
+use crate::migrations;
 
 #[cfg(feature = "embeddings")]
 use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
@@ -12,32 +19,434 @@ use sqlite_vec::sqlite3_vec_init;
 
 pub struct Db {
     conn: Connection,
+    dedup: DedupPolicy,
 }
 
-impl Db {
+/// How `insert_command` decides that a command is already saved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DedupPolicy {
+    /// The same command text anywhere is a duplicate
+    Global,
+    /// The same command text in the same working directory is a duplicate
+    #[default]
+    PerDirectory,
+    /// Never deduplicate
+    KeepAll,
+}
+
+impl DedupPolicy {
+    pub const NAMES: [&'static str; 3] = ["global", "per-directory", "keep-all"];
+}
+
+impl FromStr for DedupPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "global" => Ok(DedupPolicy::Global),
+            "per-directory" => Ok(DedupPolicy::PerDirectory),
+            "keep-all" => Ok(DedupPolicy::KeepAll),
+            _ => bail!(
+                "Unknown dedup policy '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Result of `insert_command`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SaveOutcome {
+    /// A new command was saved under the given ID
+    Inserted(String),
+    /// The command duplicated the one with this ID, which was refreshed instead
+    Updated(String),
+}
+
+impl SaveOutcome {
+    /// ID of the saved command.
+    pub fn id(&self) -> &str {
+        match self {
+            SaveOutcome::Inserted(id) | SaveOutcome::Updated(id) => id,
+        }
+    }
+}
+
+/// A saved command together with its metadata.
+#[derive(Debug, Clone)]
+pub struct SavedCommand {
+    pub id: String,
+    pub cmd: String,
+    pub description: Option<String>,
+    pub working_directory: Option<String>,
+    pub created_at: String,
+    /// Last time the command was saved again or edited
+    pub updated_at: Option<String>,
+    pub group: Option<String>,
+    pub tags: Vec<String>,
+    /// Number of times the command was picked for execution
+    pub use_count: u32,
+    pub last_used_at: Option<String>,
+}
+
+/// The user-editable fields of a saved command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandFields {
+    pub cmd: String,
+    pub description: Option<String>,
+    pub working_directory: Option<String>,
+    pub group: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl SavedCommand {
+    pub fn fields(&self) -> CommandFields {
+        CommandFields {
+            cmd: self.cmd.clone(),
+            description: self.description.clone(),
+            working_directory: self.working_directory.clone(),
+            group: self.group.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+/// Restricts which commands are returned by listing and search queries.
+/// The default filter matches every command.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ListFilter {
+    /// Only commands carrying all of these tags
+    pub tags: Vec<String>,
+    /// Only commands belonging to this group
+    pub group: Option<String>,
+    /// Only commands saved in exactly this working directory
+    pub directory: Option<String>,
+    /// Only commands saved in this working directory or one below it
+    pub under: Option<String>,
+    /// Only commands saved at or after this time (RFC 3339)
+    pub since: Option<String>,
+    /// Only commands saved before this time (RFC 3339)
+    pub until: Option<String>,
+    /// Only commands whose first word is this program
+    pub program: Option<String>,
+}
+
+/// A window into a listing, in the listing's sort order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    /// Maximum number of commands to return; `None` returns all of them
+    pub limit: Option<usize>,
+    /// Number of commands to skip first
+    pub offset: usize,
+}
+
+/// Sort order for listed commands.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Most recently saved first
+    #[default]
+    Created,
+    /// Most recently used first, never-used commands last
+    LastUsed,
+    /// Most used first
+    UseCount,
+    /// By command text, ignoring case
+    Alphabetical,
+}
+
+impl SortOrder {
+    pub const NAMES: [&'static str; 4] = ["created", "last-used", "uses", "alpha"];
+
+    fn to_sql(self) -> &'static str {
+        match self {
+            SortOrder::Created => "c.created_at DESC",
+            SortOrder::LastUsed => "c.last_used_at IS NULL, c.last_used_at DESC, c.created_at DESC",
+            SortOrder::UseCount => "c.use_count DESC, c.last_used_at DESC, c.created_at DESC",
+            SortOrder::Alphabetical => "c.cmd COLLATE NOCASE, c.cmd, c.created_at DESC",
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "created" => Ok(SortOrder::Created),
+            "last-used" => Ok(SortOrder::LastUsed),
+            "uses" => Ok(SortOrder::UseCount),
+            "alpha" => Ok(SortOrder::Alphabetical),
+            _ => bail!(
+                "Unknown sort order '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// What to search for, and how.
+#[derive(Debug, Clone, Copy)]
+pub enum Query<'a> {
+    /// Words looked up in the full-text index of command text, descriptions and tags
+    Text(&'a str),
+    /// Characters matched in order, possibly with gaps, against the command text
+    Fuzzy(&'a str),
+    /// Nearest neighbours of a query embedding
+    #[cfg(feature = "embeddings")]
+    Semantic(&'a [f32]),
+    /// Full-text and semantic results fused by reciprocal rank, for text and its embedding
+    #[cfg(feature = "embeddings")]
+    Hybrid(&'a str, &'a [f32]),
+}
+
+/// A command returned by `Db::search_commands`.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub command: SavedCommand,
+    /// Relevance of the match, higher is better. Only comparable within one search.
+    pub score: f64,
+    /// Vector distance to the query embedding, for semantic and hybrid searches
+    pub distance: Option<f64>,
+    /// Character indices in `command.cmd` matched by a fuzzy query, ascending
+    pub positions: Vec<usize>,
+}
+
+impl SearchResult {
+    fn new(command: SavedCommand, score: f64) -> Self {
+        Self {
+            command,
+            score,
+            distance: None,
+            positions: Vec::new(),
+        }
+    }
+}
+
+/// The kinds of `Query` that can be selected by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    Text,
+    Fuzzy,
+    #[cfg(feature = "embeddings")]
+    Semantic,
+    #[cfg(feature = "embeddings")]
+    Hybrid,
+}
+
+impl SearchMode {
+    #[cfg(not(feature = "embeddings"))]
+    pub const NAMES: [&'static str; 2] = ["text", "fuzzy"];
     #[cfg(feature = "embeddings")]
+    pub const NAMES: [&'static str; 4] = ["text", "fuzzy", "semantic", "hybrid"];
+}
+
+impl Default for SearchMode {
+    fn default() -> Self {
+        #[cfg(feature = "embeddings")]
+        return SearchMode::Hybrid;
+        #[cfg(not(feature = "embeddings"))]
+        return SearchMode::Text;
+    }
+}
+
+impl FromStr for SearchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(SearchMode::Text),
+            "fuzzy" => Ok(SearchMode::Fuzzy),
+            #[cfg(feature = "embeddings")]
+            "semantic" => Ok(SearchMode::Semantic),
+            #[cfg(feature = "embeddings")]
+            "hybrid" => Ok(SearchMode::Hybrid),
+            _ => bail!(
+                "Unknown search mode '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Constant of reciprocal rank fusion: a result at (1-based) rank `r` in one of the
+/// fused lists contributes `1 / (RRF_K + r)` to its hybrid score.
+#[cfg(feature = "embeddings")]
+const RRF_K: f64 = 60.0;
+
+/// How many candidates per requested result each list contributes to a hybrid search.
+#[cfg(feature = "embeddings")]
+const HYBRID_CANDIDATES: usize = 5;
+
+/// Columns selected for every `SavedCommand`, in the order expected by `SavedCommand::from_row`.
+/// Must be used together with `COMMAND_FROM`.
+const COMMAND_COLUMNS: &str =
+    "c.id, c.cmd, c.description, c.working_directory, c.created_at, g.name,
+     (SELECT group_concat(t.name, ',') FROM command_tags ct JOIN tags t ON t.id = ct.tag_id
+      WHERE ct.command_id = c.id),
+     c.use_count, c.last_used_at, c.updated_at";
+
+/// Number of columns in `COMMAND_COLUMNS`; extra selected columns start at this index.
+const COMMAND_COLUMN_COUNT: usize = 10;
+
+const COMMAND_FROM: &str = "commands c LEFT JOIN groups g ON g.id = c.group_id";
+
+impl SavedCommand {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let tags: Option<String> = row.get(6)?;
+        let mut tags: Vec<String> = tags
+            .map(|t| t.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        tags.sort();
+
+        Ok(Self {
+            id: row.get(0)?,
+            cmd: row.get(1)?,
+            description: row.get(2)?,
+            working_directory: row.get(3)?,
+            created_at: row.get(4)?,
+            group: row.get(5)?,
+            tags,
+            use_count: row.get(7)?,
+            last_used_at: row.get(8)?,
+            updated_at: row.get(9)?,
+        })
+    }
+}
+
+impl ListFilter {
+    /// Appends SQL conditions (each starting with ` AND`) for this filter, pushing their
+    /// parameters onto `params`. Conditions refer to the `c` and `g` aliases of `COMMAND_FROM`.
+    fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        let mut sql = String::new();
+
+        for tag in &self.tags {
+            params.push(Value::Text(tag.clone()));
+            sql.push_str(&format!(
+                " AND EXISTS (SELECT 1 FROM command_tags ct JOIN tags t ON t.id = ct.tag_id
+                   WHERE ct.command_id = c.id AND t.name = ?{})",
+                params.len()
+            ));
+        }
+
+        if let Some(group) = &self.group {
+            params.push(Value::Text(group.clone()));
+            sql.push_str(&format!(" AND g.name = ?{}", params.len()));
+        }
+
+        if let Some(directory) = &self.directory {
+            params.push(Value::Text(directory.clone()));
+            sql.push_str(&format!(" AND c.working_directory = ?{}", params.len()));
+        }
+
+        if let Some(under) = &self.under {
+            // Compared without LIKE, whose wildcards and case folding would need escaping
+            params.push(Value::Text(under.trim_end_matches('/').to_string()));
+            let n = params.len();
+            sql.push_str(&format!(
+                " AND (c.working_directory = ?{n}
+                   OR substr(c.working_directory, 1, length(?{n}) + 1) = ?{n} || '/')"
+            ));
+        }
+
+        // julianday() normalizes the time zone offsets of RFC 3339 timestamps
+        if let Some(since) = &self.since {
+            params.push(Value::Text(since.clone()));
+            sql.push_str(&format!(
+                " AND julianday(c.created_at) >= julianday(?{})",
+                params.len()
+            ));
+        }
+
+        if let Some(until) = &self.until {
+            params.push(Value::Text(until.clone()));
+            sql.push_str(&format!(
+                " AND julianday(c.created_at) < julianday(?{})",
+                params.len()
+            ));
+        }
+
+        if let Some(program) = &self.program {
+            params.push(Value::Text(program.clone()));
+            let n = params.len();
+            sql.push_str(&format!(
+                " AND (c.cmd = ?{n}
+                   OR substr(c.cmd, 1, length(?{n}) + 1) IN (?{n} || ' ', ?{n} || char(9), ?{n} || char(10)))"
+            ));
+        }
+
+        sql
+    }
+}
+
+/// Turns free text into an FTS5 query, or `None` if it contains no searchable token.
+///
+/// Words are split on whitespace and may match in any order. Within a word, shell
+/// punctuation separates tokens the same way the index tokenizer does, and the
+/// resulting tokens must appear in sequence (`docker-compose` matches "docker compose").
+/// The last token of each word matches as a prefix.
+fn fts_query(query: &str) -> Option<String> {
+    let phrases: Vec<String> = query
+        .split_whitespace()
+        .filter_map(|word| {
+            let tokens: Vec<&str> = word
+                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .filter(|t| !t.is_empty())
+                .collect();
+            if tokens.is_empty() {
+                None
+            } else {
+                Some(format!("\"{}\"*", tokens.join(" ")))
+            }
+        })
+        .collect();
+
+    if phrases.is_empty() {
+        None
+    } else {
+        Some(phrases.join(" "))
+    }
+}
+
+/// Validates a tag or group name and returns it trimmed.
+pub fn normalize_label(kind: &str, name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("{} name cannot be empty", kind);
+    }
+    if name.contains(',') || name.chars().any(char::is_whitespace) {
+        bail!(
+            "Invalid {} name '{}': commas and whitespace are not allowed",
+            kind.to_lowercase(),
+            name
+        );
+    }
+    Ok(name.to_string())
+}
+
+impl Db {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         // Initialize sqlite-vec extension
+        #[cfg(feature = "embeddings")]
         #[allow(clippy::missing_transmute_annotations)]
         unsafe {
             sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_vec_init as *const ())));
         }
 
-        let conn = Connection::open(path)?;
+        let mut conn = Connection::open(path)?;
 
-        // Create commands table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS commands (
-                id TEXT PRIMARY KEY,
-                cmd TEXT NOT NULL,
-                description TEXT,
-                working_directory TEXT,
-                created_at TEXT NOT NULL
-            )",
-            [],
-        )?;
+        // Needed for tag and group cleanup when commands are deleted
+        conn.pragma_update(None, "foreign_keys", true)?;
 
-        // Create cmd_embeddings virtual table
+        // Bring the schema up to date before anything else touches it
+        migrations::run(&mut conn)?;
+
+        // Create cmd_embeddings virtual table. It lives outside the versioned
+        // migrations because it needs the sqlite-vec extension, which the
+        // default build does not load.
+        #[cfg(feature = "embeddings")]
         conn.execute(
             "CREATE VIRTUAL TABLE IF NOT EXISTS cmd_embeddings USING vec0(
                 cmd_id TEXT PRIMARY KEY,
@@ -46,28 +455,21 @@ impl Db {
             [],
         )?;
 
-        Ok(Self { conn })
+        Ok(Self {
+            conn,
+            dedup: DedupPolicy::default(),
+        })
     }
 
-    #[cfg(not(feature = "embeddings"))]
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let conn = Connection::open(path)?;
-
-        // Create commands table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS commands (
-                id TEXT PRIMARY KEY,
-                cmd TEXT NOT NULL,
-                description TEXT,
-                working_directory TEXT,
-                created_at TEXT NOT NULL
-            )",
-            [],
-        )?;
-
-        Ok(Self { conn })
+    /// Sets the policy used by `insert_command` to detect duplicates.
+    pub fn with_dedup_policy(mut self, policy: DedupPolicy) -> Self {
+        self.dedup = policy;
+        self
     }
 
+    /// Saves a command, or refreshes an existing duplicate according to the dedup policy.
+    /// A refreshed duplicate keeps its ID, tags and usage; its embedding is only replaced
+    /// when a new description is given.
     #[cfg(feature = "embeddings")]
     pub fn insert_command(
         &self,
@@ -76,37 +478,34 @@ impl Db {
         description: Option<&str>,
         working_dir: Option<&str>,
         embedding: &[f32],
-    ) -> Result<()> {
-        // Check for existing command and delete it if found
-        {
-            let mut stmt = self
-                .conn
-                .prepare("SELECT id FROM commands WHERE cmd = ?1")?;
-            let mut rows = stmt.query(params![cmd])?;
-            if let Some(row) = rows.next()? {
-                let old_id: String = row.get(0)?;
-                self.delete_command(&old_id)?;
-            }
+    ) -> Result<SaveOutcome> {
+        let savepoint = self.savepoint()?;
+        let outcome = self.upsert_command(id, cmd, description, working_dir)?;
+        if matches!(outcome, SaveOutcome::Inserted(_)) || description.is_some() {
+            self.store_embedding(outcome.id(), embedding)?;
         }
+        savepoint.commit()?;
+        Ok(outcome)
+    }
 
-        let created_at = Utc::now().to_rfc3339();
-
-        self.conn.execute(
-            "INSERT INTO commands (id, cmd, description, working_directory, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![id, cmd, description, working_dir, created_at],
-        )?;
-
+    /// Stores the embedding of a command, replacing any previous one.
+    #[cfg(feature = "embeddings")]
+    fn store_embedding(&self, id: &str, embedding: &[f32]) -> Result<()> {
         // Convert &[f32] to bytes for sqlite-vec
         let embedding_bytes: Vec<u8> = embedding.iter().flat_map(|f| f.to_ne_bytes()).collect();
 
+        let savepoint = self.savepoint()?;
+        self.conn
+            .execute("DELETE FROM cmd_embeddings WHERE cmd_id = ?1", params![id])?;
         self.conn.execute(
             "INSERT INTO cmd_embeddings (cmd_id, embedding) VALUES (?1, ?2)",
             params![id, embedding_bytes],
         )?;
-
-        Ok(())
+        savepoint.commit()
     }
 
+    /// Saves a command, or refreshes an existing duplicate according to the dedup policy.
+    /// A refreshed duplicate keeps its ID, tags and usage.
     #[cfg(not(feature = "embeddings"))]
     pub fn insert_command(
         &self,
@@ -114,56 +513,173 @@ impl Db {
         cmd: &str,
         description: Option<&str>,
         working_dir: Option<&str>,
-    ) -> Result<()> {
-        // Check for existing command and delete it if found
-        {
-            let mut stmt = self
-                .conn
-                .prepare("SELECT id FROM commands WHERE cmd = ?1")?;
-            let mut rows = stmt.query(params![cmd])?;
-            if let Some(row) = rows.next()? {
-                let old_id: String = row.get(0)?;
-                self.delete_command(&old_id)?;
-            }
-        }
+    ) -> Result<SaveOutcome> {
+        self.upsert_command(id, cmd, description, working_dir)
+    }
+
+    fn upsert_command(
+        &self,
+        id: &str,
+        cmd: &str,
+        description: Option<&str>,
+        working_dir: Option<&str>,
+    ) -> Result<SaveOutcome> {
+        let now = Utc::now().to_rfc3339();
 
-        let created_at = Utc::now().to_rfc3339();
+        if let Some(existing) = self.find_duplicate(cmd, working_dir)? {
+            self.conn.execute(
+                "UPDATE commands
+                 SET updated_at = ?1, working_directory = ?2, description = COALESCE(?3, description)
+                 WHERE id = ?4",
+                params![now, working_dir, description, existing],
+            )?;
+            return Ok(SaveOutcome::Updated(existing));
+        }
 
         self.conn.execute(
-            "INSERT INTO commands (id, cmd, description, working_directory, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![id, cmd, description, working_dir, created_at],
+            "INSERT INTO commands (id, cmd, description, working_directory, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+            params![id, cmd, description, working_dir, now],
         )?;
 
-        Ok(())
+        Ok(SaveOutcome::Inserted(id.to_string()))
     }
 
-    #[cfg(feature = "embeddings")]
+    /// Returns the ID of a saved command that the dedup policy considers a duplicate
+    /// of `cmd` run in `working_dir`, if any.
+    pub fn find_duplicate(&self, cmd: &str, working_dir: Option<&str>) -> Result<Option<String>> {
+        let id = match self.dedup {
+            DedupPolicy::Global => self
+                .conn
+                .query_row(
+                    "SELECT id FROM commands WHERE cmd = ?1 ORDER BY created_at LIMIT 1",
+                    params![cmd],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            DedupPolicy::PerDirectory => self
+                .conn
+                .query_row(
+                    "SELECT id FROM commands WHERE cmd = ?1 AND working_directory IS ?2
+                     ORDER BY created_at LIMIT 1",
+                    params![cmd, working_dir],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            DedupPolicy::KeepAll => None,
+        };
+        Ok(id)
+    }
+
+    /// Searches saved commands matching `filter`, best matches first.
     pub fn search_commands(
         &self,
+        query: Query,
+        limit: usize,
+        filter: &ListFilter,
+    ) -> Result<Vec<SearchResult>> {
+        match query {
+            Query::Text(text) => self.search_lexical(text, limit, filter),
+            Query::Fuzzy(pattern) => self.search_fuzzy(pattern, limit, filter),
+            #[cfg(feature = "embeddings")]
+            Query::Semantic(embedding) => self.search_semantic(embedding, limit, filter),
+            #[cfg(feature = "embeddings")]
+            Query::Hybrid(text, embedding) => self.search_hybrid(text, embedding, limit, filter),
+        }
+    }
+
+    fn search_lexical(
+        &self,
+        text: &str,
+        limit: usize,
+        filter: &ListFilter,
+    ) -> Result<Vec<SearchResult>> {
+        match fts_query(text) {
+            Some(fts_query) => self.search_fulltext(&fts_query, limit, filter),
+            // Nothing to tokenize (e.g. a lone `|`): fall back to a substring match
+            None => self.search_like(text, limit, filter),
+        }
+    }
+
+    /// Fuses the full-text and semantic rankings with reciprocal rank fusion, so that
+    /// commands literally matching the query stay on top of merely similar ones.
+    #[cfg(feature = "embeddings")]
+    fn search_hybrid(
+        &self,
+        text: &str,
         query_embedding: &[f32],
         limit: usize,
-    ) -> Result<Vec<(String, String, Option<String>, f32)>> {
+        filter: &ListFilter,
+    ) -> Result<Vec<SearchResult>> {
+        let candidates = limit * HYBRID_CANDIDATES;
+        let lexical = self.search_lexical(text, candidates, filter)?;
+        let semantic = self.search_semantic(query_embedding, candidates, filter)?;
+
+        let mut fused: Vec<SearchResult> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for ranking in [lexical, semantic] {
+            for (rank, result) in ranking.into_iter().enumerate() {
+                let score = 1.0 / (RRF_K + rank as f64 + 1.0);
+                match positions.get(&result.command.id) {
+                    Some(&i) => {
+                        fused[i].score += score;
+                        fused[i].distance = fused[i].distance.or(result.distance);
+                    }
+                    None => {
+                        positions.insert(result.command.id.clone(), fused.len());
+                        fused.push(SearchResult { score, ..result });
+                    }
+                }
+            }
+        }
+
+        // Stable sort: ties keep the full-text order
+        fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+        fused.truncate(limit);
+
+        Ok(fused)
+    }
+
+    #[cfg(feature = "embeddings")]
+    fn search_semantic(
+        &self,
+        query_embedding: &[f32],
+        limit: usize,
+        filter: &ListFilter,
+    ) -> Result<Vec<SearchResult>> {
         let embedding_bytes: Vec<u8> = query_embedding
             .iter()
             .flat_map(|f| f.to_ne_bytes())
             .collect();
 
-        let mut stmt = self.conn.prepare(
-            "SELECT c.id, c.cmd, c.working_directory, e.distance 
-             FROM cmd_embeddings e
-             JOIN commands c ON c.id = e.cmd_id
-             WHERE e.embedding MATCH ?1 
-               AND k = ?2
-             ORDER BY e.distance",
-        )?;
+        // The KNN search runs before the filter is applied, so over-fetch when
+        // filtering to still have a chance of returning `limit` results.
+        let mut params = vec![Value::Blob(embedding_bytes), Value::Integer(0)];
+        let filter_sql = filter.to_sql(&mut params);
+        let k = if filter_sql.is_empty() {
+            limit
+        } else {
+            limit * 10
+        };
+        params[1] = Value::Integer(k as i64);
+        params.push(Value::Integer(limit as i64));
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {COMMAND_COLUMNS}, e.distance
+             FROM {COMMAND_FROM}
+             JOIN cmd_embeddings e ON e.cmd_id = c.id
+             WHERE e.embedding MATCH ?1
+               AND k = ?2{filter_sql}
+             ORDER BY e.distance
+             LIMIT ?{}",
+            params.len()
+        ))?;
 
-        let rows = stmt.query_map(params![embedding_bytes, limit as i64], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, Option<String>>(2)?,
-                row.get::<_, f32>(3)?,
-            ))
+        let rows = stmt.query_map(params_from_iter(params), |row| {
+            let distance: f64 = row.get(COMMAND_COLUMN_COUNT)?;
+            Ok(SearchResult {
+                distance: Some(distance),
+                ..SearchResult::new(SavedCommand::from_row(row)?, -distance)
+            })
         })?;
 
         let mut results = Vec::new();
@@ -174,23 +690,62 @@ impl Db {
         Ok(results)
     }
 
-    #[cfg(not(feature = "embeddings"))]
-    pub fn search_commands(
+    /// Every word of the query must match, in any order, as a prefix of an indexed word.
+    fn search_fulltext(
+        &self,
+        fts_query: &str,
+        limit: usize,
+        filter: &ListFilter,
+    ) -> Result<Vec<SearchResult>> {
+        let mut params = vec![Value::Text(fts_query.to_string())];
+        let filter_sql = filter.to_sql(&mut params);
+        params.push(Value::Integer(limit as i64));
+
+        // A match in the command text weighs more than one in the description or tags.
+        // bm25() is negative, lower meaning more relevant.
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {COMMAND_COLUMNS}, bm25(commands_fts, 0.0, 10.0, 4.0, 2.0) AS rank
+             FROM {COMMAND_FROM}
+             JOIN commands_fts ON commands_fts.id = c.id
+             WHERE commands_fts MATCH ?1{filter_sql}
+             ORDER BY rank, c.use_count DESC
+             LIMIT ?{}",
+            params.len()
+        ))?;
+
+        let rows = stmt.query_map(params_from_iter(params), |row| {
+            let rank: f64 = row.get(COMMAND_COLUMN_COUNT)?;
+            Ok(SearchResult::new(SavedCommand::from_row(row)?, -rank))
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+
+        Ok(results)
+    }
+
+    fn search_like(
         &self,
         query: &str,
         limit: usize,
-    ) -> Result<Vec<(String, String, Option<String>)>> {
-        let search_pattern = format!("%{}%", query);
-        let mut stmt = self.conn.prepare(
-            "SELECT id, cmd, working_directory FROM commands WHERE cmd LIKE ?1 ORDER BY created_at DESC LIMIT ?2",
-        )?;
+        filter: &ListFilter,
+    ) -> Result<Vec<SearchResult>> {
+        let mut params = vec![Value::Text(format!("%{}%", query))];
+        let filter_sql = filter.to_sql(&mut params);
+        params.push(Value::Integer(limit as i64));
 
-        let rows = stmt.query_map(params![search_pattern, limit as i64], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, Option<String>>(2)?,
-            ))
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {COMMAND_COLUMNS} FROM {COMMAND_FROM}
+             WHERE (c.cmd LIKE ?1 OR c.description LIKE ?1){filter_sql}
+             ORDER BY c.created_at DESC
+             LIMIT ?{}",
+            params.len()
+        ))?;
+
+        let rows = stmt.query_map(params_from_iter(params), |row| {
+            Ok(SearchResult::new(SavedCommand::from_row(row)?, 0.0))
         })?;
 
         let mut results = Vec::new();
@@ -201,12 +756,254 @@ impl Db {
         Ok(results)
     }
 
-    pub fn get_all_commands(&self) -> Result<Vec<(String, String)>> {
-        let mut stmt = self
+    /// Scores every command matching `filter`, so this is linear in the number of saved commands.
+    fn search_fuzzy(
+        &self,
+        pattern: &str,
+        limit: usize,
+        filter: &ListFilter,
+    ) -> Result<Vec<SearchResult>> {
+        let mut results: Vec<SearchResult> = self
+            .list_commands(filter, SortOrder::UseCount)?
+            .into_iter()
+            .filter_map(|command| {
+                let m = fuzzy::fuzzy_match(pattern, &command.cmd)?;
+                Some(SearchResult {
+                    positions: m.positions,
+                    ..SearchResult::new(command, m.score as f64)
+                })
+            })
+            .collect();
+
+        // Stable sort: equal scores keep the most used command first, then the shortest
+        results.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a.command.cmd.len().cmp(&b.command.cmd.len()))
+        });
+        results.truncate(limit);
+
+        Ok(results)
+    }
+
+    pub fn list_commands(&self, filter: &ListFilter, sort: SortOrder) -> Result<Vec<SavedCommand>> {
+        self.list_page(filter, sort, Page::default())
+    }
+
+    /// Lists the commands matching `filter` within `page` of the sorted listing.
+    pub fn list_page(
+        &self,
+        filter: &ListFilter,
+        sort: SortOrder,
+        page: Page,
+    ) -> Result<Vec<SavedCommand>> {
+        let mut params = Vec::new();
+        let filter_sql = filter.to_sql(&mut params);
+        // A negative LIMIT means no limit in SQLite
+        params.push(Value::Integer(page.limit.map_or(-1, |limit| limit as i64)));
+        params.push(Value::Integer(page.offset as i64));
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {COMMAND_COLUMNS} FROM {COMMAND_FROM}
+             WHERE 1 = 1{filter_sql}
+             ORDER BY {}
+             LIMIT ?{} OFFSET ?{}",
+            sort.to_sql(),
+            params.len() - 1,
+            params.len()
+        ))?;
+        let rows = stmt.query_map(params_from_iter(params), SavedCommand::from_row)?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+
+        Ok(results)
+    }
+
+    /// Counts the commands matching `filter`.
+    pub fn count_commands(&self, filter: &ListFilter) -> Result<usize> {
+        let mut params = Vec::new();
+        let filter_sql = filter.to_sql(&mut params);
+
+        let count: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM {COMMAND_FROM} WHERE 1 = 1{filter_sql}"),
+            params_from_iter(params),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Fetches a single command by its ID.
+    pub fn get_command(&self, id: &str) -> Result<Option<SavedCommand>> {
+        let command = self
             .conn
-            .prepare("SELECT id, cmd FROM commands ORDER BY created_at DESC")?;
+            .query_row(
+                &format!("SELECT {COMMAND_COLUMNS} FROM {COMMAND_FROM} WHERE c.id = ?1"),
+                params![id],
+                SavedCommand::from_row,
+            )
+            .optional()?;
+        Ok(command)
+    }
+
+    /// Sets or, when `description` is `None`, clears the description of a command.
+    /// The embedding must be computed from the command text and the new description.
+    #[cfg(feature = "embeddings")]
+    pub fn set_description(
+        &self,
+        id: &str,
+        description: Option<&str>,
+        embedding: &[f32],
+    ) -> Result<()> {
+        let savepoint = self.savepoint()?;
+        self.update_description(id, description)?;
+        self.store_embedding(id, embedding)?;
+        savepoint.commit()
+    }
+
+    /// Sets or, when `description` is `None`, clears the description of a command.
+    #[cfg(not(feature = "embeddings"))]
+    pub fn set_description(&self, id: &str, description: Option<&str>) -> Result<()> {
+        self.update_description(id, description)
+    }
+
+    fn update_description(&self, id: &str, description: Option<&str>) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE commands SET description = ?1 WHERE id = ?2",
+            params![description, id],
+        )?;
+        if updated == 0 {
+            bail!("No command found with ID '{}'", id);
+        }
+        Ok(())
+    }
+
+    /// Resolves a full command ID from a unique prefix of it, like git does for commits.
+    pub fn resolve_id(&self, prefix: &str) -> Result<String> {
+        let prefix = prefix.trim();
+        if prefix.is_empty() {
+            bail!("Command ID cannot be empty");
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, cmd FROM commands
+             WHERE substr(id, 1, length(?1)) = ?1
+             ORDER BY id = ?1 DESC, created_at DESC",
+        )?;
+        let candidates = stmt
+            .query_map(params![prefix], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        match candidates.as_slice() {
+            [] => bail!("No command found with ID '{}'", prefix),
+            // An exact match wins even if it is also the prefix of other IDs
+            [(id, _), ..] if id == prefix => Ok(id.clone()),
+            [(id, _)] => Ok(id.clone()),
+            candidates => {
+                let list: Vec<String> = candidates
+                    .iter()
+                    .map(|(id, cmd)| format!("  {}  {}", id, cmd))
+                    .collect();
+                bail!(
+                    "ID prefix '{}' is ambiguous, it matches:\n{}",
+                    prefix,
+                    list.join("\n")
+                )
+            }
+        }
+    }
+
+    /// Replaces the editable fields of a command, keeping its ID and usage statistics.
+    /// The embedding must be computed from the new command text and description.
+    #[cfg(feature = "embeddings")]
+    pub fn update_command(
+        &self,
+        id: &str,
+        fields: &CommandFields,
+        embedding: &[f32],
+    ) -> Result<()> {
+        let savepoint = self.savepoint()?;
+        self.update_fields(id, fields)?;
+        self.store_embedding(id, embedding)?;
+        savepoint.commit()
+    }
+
+    /// Replaces the editable fields of a command, keeping its ID and usage statistics.
+    #[cfg(not(feature = "embeddings"))]
+    pub fn update_command(&self, id: &str, fields: &CommandFields) -> Result<()> {
+        let savepoint = self.savepoint()?;
+        self.update_fields(id, fields)?;
+        savepoint.commit()
+    }
+
+    fn update_fields(&self, id: &str, fields: &CommandFields) -> Result<()> {
+        if fields.cmd.trim().is_empty() {
+            bail!("Command cannot be empty");
+        }
+
+        let updated_at = Utc::now().to_rfc3339();
+        let updated = self.conn.execute(
+            "UPDATE commands SET cmd = ?1, description = ?2, working_directory = ?3, updated_at = ?4
+             WHERE id = ?5",
+            params![
+                fields.cmd,
+                fields.description,
+                fields.working_directory,
+                updated_at,
+                id
+            ],
+        )?;
+        if updated == 0 {
+            bail!("No command found with ID '{}'", id);
+        }
+
+        self.conn.execute(
+            "DELETE FROM command_tags WHERE command_id = ?1",
+            params![id],
+        )?;
+        self.add_tags(id, &fields.tags)?;
+        self.set_group(id, fields.group.as_deref())
+    }
+
+    /// Records that a command was picked for execution.
+    pub fn record_use(&self, id: &str) -> Result<()> {
+        let used_at = Utc::now().to_rfc3339();
+        let updated = self.conn.execute(
+            "UPDATE commands SET use_count = use_count + 1, last_used_at = ?1 WHERE id = ?2",
+            params![used_at, id],
+        )?;
+        if updated == 0 {
+            bail!("No command found with ID '{}'", id);
+        }
+        Ok(())
+    }
+
+    /// Returns every tag in use, with the number of commands carrying it.
+    pub fn list_tags(&self) -> Result<Vec<(String, usize)>> {
+        self.count_labels(
+            "SELECT t.name, COUNT(*) FROM tags t
+             JOIN command_tags ct ON ct.tag_id = t.id
+             GROUP BY t.id ORDER BY t.name",
+        )
+    }
+
+    /// Returns every group in use, with the number of commands in it.
+    pub fn list_groups(&self) -> Result<Vec<(String, usize)>> {
+        self.count_labels(
+            "SELECT g.name, COUNT(*) FROM groups g
+             JOIN commands c ON c.group_id = g.id
+             GROUP BY g.id ORDER BY g.name",
+        )
+    }
+
+    fn count_labels(&self, sql: &str) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(sql)?;
         let rows = stmt.query_map([], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
         })?;
 
         let mut results = Vec::new();
@@ -217,23 +1014,274 @@ impl Db {
         Ok(results)
     }
 
+    /// Attaches tags to a command. Tags the command already has are left untouched.
+    pub fn add_tags(&self, id: &str, tags: &[String]) -> Result<()> {
+        self.ensure_exists(id)?;
+        let savepoint = self.savepoint()?;
+        for tag in tags {
+            let tag = normalize_label("Tag", tag)?;
+            self.conn.execute(
+                "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+                params![tag],
+            )?;
+            self.conn.execute(
+                "INSERT OR IGNORE INTO command_tags (command_id, tag_id)
+                 SELECT ?1, id FROM tags WHERE name = ?2",
+                params![id, tag],
+            )?;
+        }
+        savepoint.commit()
+    }
+
+    /// Detaches tags from a command. Tags the command does not have are ignored.
+    pub fn remove_tags(&self, id: &str, tags: &[String]) -> Result<()> {
+        self.ensure_exists(id)?;
+        for tag in tags {
+            self.conn.execute(
+                "DELETE FROM command_tags
+                 WHERE command_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+                params![id, tag.trim()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Moves a command into a group, or out of any group when `group` is `None`.
+    pub fn set_group(&self, id: &str, group: Option<&str>) -> Result<()> {
+        self.ensure_exists(id)?;
+        let savepoint = self.savepoint()?;
+        let group_id = match group {
+            Some(group) => {
+                let group = normalize_label("Group", group)?;
+                self.conn.execute(
+                    "INSERT OR IGNORE INTO groups (name) VALUES (?1)",
+                    params![group],
+                )?;
+                Some(self.conn.query_row(
+                    "SELECT id FROM groups WHERE name = ?1",
+                    params![group],
+                    |row| row.get::<_, i64>(0),
+                )?)
+            }
+            None => None,
+        };
+        self.conn.execute(
+            "UPDATE commands SET group_id = ?1 WHERE id = ?2",
+            params![group_id, id],
+        )?;
+        savepoint.commit()
+    }
+
+    fn ensure_exists(&self, id: &str) -> Result<()> {
+        let found = self
+            .conn
+            .query_row("SELECT 1 FROM commands WHERE id = ?1", params![id], |_| {
+                Ok(())
+            })
+            .optional()?;
+        if found.is_none() {
+            bail!("No command found with ID '{}'", id);
+        }
+        Ok(())
+    }
+
     pub fn clear_commands(&self) -> Result<()> {
+        let savepoint = self.savepoint()?;
         self.conn.execute("DELETE FROM commands", [])?;
         #[cfg(feature = "embeddings")]
         self.conn.execute("DELETE FROM cmd_embeddings", [])?;
-        Ok(())
+        savepoint.commit()
     }
 
     pub fn delete_command(&self, id: &str) -> Result<()> {
+        let savepoint = self.savepoint()?;
         self.conn
             .execute("DELETE FROM commands WHERE id = ?1", params![id])?;
         #[cfg(feature = "embeddings")]
         self.conn
-            .execute("DELETE FROM cmd_embeddings WHERE cmd_id = ?1", params![id])?;
+            .execute("DELETE FROM cmd_embeddings WHERE cmd_id = ?1", params![id])?;
+        savepoint.commit()
+    }
+
+    /// Looks for rows that reference data which no longer exists, and, in the
+    /// embeddings build, for commands that cannot be found by semantic search.
+    pub fn check_consistency(&self) -> Result<ConsistencyReport> {
+        let mut report = ConsistencyReport::default();
+
+        // Catches tag links and groups left dangling by writes made without foreign keys
+        let mut stmt = self.conn.prepare("PRAGMA foreign_key_check")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        for row in rows {
+            report.dangling_references.push(row?);
+        }
+
+        #[cfg(feature = "embeddings")]
+        {
+            let mut stmt = self.conn.prepare(
+                "SELECT cmd_id FROM cmd_embeddings
+                 WHERE cmd_id NOT IN (SELECT id FROM commands)",
+            )?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            for row in rows {
+                report.orphaned_embeddings.push(row?);
+            }
+
+            let mut stmt = self.conn.prepare(
+                "SELECT id FROM commands
+                 WHERE id NOT IN (SELECT cmd_id FROM cmd_embeddings)",
+            )?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            for row in rows {
+                report.missing_embeddings.push(row?);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Fixes the problems found by `check_consistency` in a single transaction:
+    /// dangling references and orphaned embeddings are removed and missing
+    /// embeddings are computed with `embed`.
+    #[cfg(feature = "embeddings")]
+    pub fn repair(
+        &self,
+        report: &ConsistencyReport,
+        embed: &mut dyn FnMut(&str) -> Result<Vec<f32>>,
+    ) -> Result<()> {
+        let savepoint = self.savepoint()?;
+        self.remove_dangling_references(report)?;
+        for id in &report.orphaned_embeddings {
+            self.conn
+                .execute("DELETE FROM cmd_embeddings WHERE cmd_id = ?1", params![id])?;
+        }
+        for id in &report.missing_embeddings {
+            if let Some(command) = self.get_command(id)? {
+                let embedding = embed(&embedding_text(
+                    &command.cmd,
+                    command.description.as_deref(),
+                ))?;
+                self.store_embedding(id, &embedding)?;
+            }
+        }
+        savepoint.commit()
+    }
+
+    /// Fixes the problems found by `check_consistency` in a single transaction.
+    #[cfg(not(feature = "embeddings"))]
+    pub fn repair(&self, report: &ConsistencyReport) -> Result<()> {
+        let savepoint = self.savepoint()?;
+        self.remove_dangling_references(report)?;
+        savepoint.commit()
+    }
+
+    fn remove_dangling_references(&self, report: &ConsistencyReport) -> Result<()> {
+        for (table, rowid) in &report.dangling_references {
+            match table.as_str() {
+                "command_tags" => self
+                    .conn
+                    .execute("DELETE FROM command_tags WHERE rowid = ?1", params![rowid])?,
+                "commands" => self.conn.execute(
+                    "UPDATE commands SET group_id = NULL WHERE rowid = ?1",
+                    params![rowid],
+                )?,
+                _ => bail!(
+                    "Don't know how to repair a dangling reference in '{}'",
+                    table
+                ),
+            };
+        }
+        Ok(())
+    }
+
+    /// Overwrites the use count of a command, e.g. when importing usage statistics.
+    pub fn set_use_count(&self, id: &str, use_count: u32) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE commands SET use_count = ?1 WHERE id = ?2",
+            params![use_count, id],
+        )?;
+        if updated == 0 {
+            bail!("No command found with ID '{}'", id);
+        }
+        Ok(())
+    }
+
+    /// Opens a savepoint. Every change made through this `Db` until the savepoint
+    /// is committed is rolled back if it is dropped instead. Savepoints nest.
+    pub fn savepoint(&self) -> Result<Savepoint<'_>> {
+        self.conn.execute_batch("SAVEPOINT komando")?;
+        Ok(Savepoint {
+            conn: &self.conn,
+            finished: false,
+        })
+    }
+}
+
+/// Problems found by [`Db::check_consistency`].
+#[derive(Debug, Default, PartialEq)]
+pub struct ConsistencyReport {
+    /// `(table, rowid)` of rows referencing a command, tag or group that does not exist
+    pub dangling_references: Vec<(String, i64)>,
+    /// IDs of embeddings whose command no longer exists
+    #[cfg(feature = "embeddings")]
+    pub orphaned_embeddings: Vec<String>,
+    /// IDs of commands without an embedding, which semantic search cannot find
+    #[cfg(feature = "embeddings")]
+    pub missing_embeddings: Vec<String>,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        #[cfg(feature = "embeddings")]
+        if !self.orphaned_embeddings.is_empty() || !self.missing_embeddings.is_empty() {
+            return false;
+        }
+        self.dangling_references.is_empty()
+    }
+}
+
+/// Guard returned by [`Db::savepoint`].
+pub struct Savepoint<'a> {
+    conn: &'a Connection,
+    finished: bool,
+}
+
+impl Savepoint<'_> {
+    pub fn commit(mut self) -> Result<()> {
+        self.finished = true;
+        self.conn.execute_batch("RELEASE komando")?;
+        Ok(())
+    }
+
+    pub fn rollback(mut self) -> Result<()> {
+        self.finished = true;
+        self.conn
+            .execute_batch("ROLLBACK TO komando; RELEASE komando")?;
         Ok(())
     }
 }
 
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self
+                .conn
+                .execute_batch("ROLLBACK TO komando; RELEASE komando");
+        }
+    }
+}
+
+/// Text that is embedded for a command, so that semantic search also
+/// considers its description.
+#[cfg(feature = "embeddings")]
+pub fn embedding_text(cmd: &str, description: Option<&str>) -> String {
+    match description {
+        Some(description) => format!("{}\n{}", cmd, description),
+        None => cmd.to_string(),
+    }
+}
+
 #[cfg(feature = "embeddings")]
 pub struct Embedder {
     model: TextEmbedding,
@@ -270,7 +1318,9 @@ mod tests {
         let db = Db::new(temp_file.path()).unwrap();
 
         // Verify table exists by querying it
-        let commands = db.get_all_commands().unwrap();
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
         assert_eq!(commands.len(), 0);
     }
 
@@ -287,10 +1337,12 @@ mod tests {
 
         db.insert_command(id, cmd, desc, wd).unwrap();
 
-        let commands = db.get_all_commands().unwrap();
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
         assert_eq!(commands.len(), 1);
-        assert_eq!(commands[0].0, id);
-        assert_eq!(commands[0].1, cmd);
+        assert_eq!(commands[0].id, id);
+        assert_eq!(commands[0].cmd, cmd);
     }
 
     #[test]
@@ -306,12 +1358,16 @@ mod tests {
         db.insert_command("id3", "docker ps -a", None, None)
             .unwrap();
 
-        let results = db.search_commands("git", 10).unwrap();
+        let results = db
+            .search_commands(Query::Text("git"), 10, &ListFilter::default())
+            .unwrap();
         assert_eq!(results.len(), 2);
 
-        let results = db.search_commands("docker", 10).unwrap();
+        let results = db
+            .search_commands(Query::Text("docker"), 10, &ListFilter::default())
+            .unwrap();
         assert_eq!(results.len(), 1);
-        assert_eq!(results[0].1, "docker ps -a");
+        assert_eq!(results[0].command.cmd, "docker ps -a");
     }
 
     #[test]
@@ -331,12 +1387,16 @@ mod tests {
                 .unwrap();
         }
 
-        let commands = db.get_all_commands().unwrap();
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
         assert_eq!(commands.len(), 1);
 
         db.delete_command(id).unwrap();
 
-        let commands = db.get_all_commands().unwrap();
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
         assert_eq!(commands.len(), 0);
     }
 
@@ -368,7 +1428,725 @@ mod tests {
             }
         }
 
-        let commands = db.get_all_commands().unwrap();
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
         assert_eq!(commands.len(), 5);
     }
+
+    #[cfg(not(feature = "embeddings"))]
+    fn insert(db: &Db, id: &str, cmd: &str) {
+        db.insert_command(id, cmd, None, None).unwrap();
+    }
+
+    #[cfg(feature = "embeddings")]
+    fn insert(db: &Db, id: &str, cmd: &str) {
+        db.insert_command(id, cmd, None, None, &[0.1f32; 384])
+            .unwrap();
+    }
+
+    fn save_in(db: &Db, id: &str, cmd: &str, dir: &str) -> SaveOutcome {
+        #[cfg(not(feature = "embeddings"))]
+        let outcome = db.insert_command(id, cmd, None, Some(dir));
+        #[cfg(feature = "embeddings")]
+        let outcome = db.insert_command(id, cmd, None, Some(dir), &[0.1f32; 384]);
+        outcome.unwrap()
+    }
+
+    #[test]
+    fn test_tags_and_groups() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "cargo build");
+        insert(&db, "id2", "cargo test");
+        insert(&db, "id3", "docker ps");
+
+        db.add_tags("id1", &["rust".to_string(), "build".to_string()])
+            .unwrap();
+        db.add_tags("id2", &["rust".to_string()]).unwrap();
+        db.set_group("id1", Some("komando")).unwrap();
+        db.set_group("id3", Some("infra")).unwrap();
+
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
+        let first = commands.iter().find(|c| c.id == "id1").unwrap();
+        assert_eq!(first.tags, vec!["build", "rust"]);
+        assert_eq!(first.group.as_deref(), Some("komando"));
+
+        let filter = ListFilter {
+            tags: vec!["rust".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            db.list_commands(&filter, SortOrder::Created).unwrap().len(),
+            2
+        );
+
+        let filter = ListFilter {
+            tags: vec!["rust".to_string(), "build".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            db.list_commands(&filter, SortOrder::Created).unwrap().len(),
+            1
+        );
+
+        let filter = ListFilter {
+            group: Some("infra".to_string()),
+            ..Default::default()
+        };
+        let commands = db.list_commands(&filter, SortOrder::Created).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].id, "id3");
+
+        db.remove_tags("id1", &["build".to_string()]).unwrap();
+        db.set_group("id1", None).unwrap();
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
+        let first = commands.iter().find(|c| c.id == "id1").unwrap();
+        assert_eq!(first.tags, vec!["rust"]);
+        assert_eq!(first.group, None);
+
+        assert_eq!(db.list_tags().unwrap(), vec![("rust".to_string(), 2)]);
+        assert_eq!(db.list_groups().unwrap(), vec![("infra".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_list_filters() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        save_in(&db, "id1", "git status", "/srv/app");
+        save_in(&db, "id2", "git\tlog", "/srv/app/web");
+        save_in(&db, "id3", "gitk --all", "/srv/application");
+        save_in(&db, "id4", "docker ps", "/home");
+        for (id, created_at) in [
+            ("id1", "2024-01-10T12:00:00+00:00"),
+            ("id2", "2024-02-10T12:00:00+00:00"),
+            ("id3", "2024-03-10T12:00:00+02:00"),
+            ("id4", "2024-03-10T11:00:00+00:00"),
+        ] {
+            db.conn
+                .execute(
+                    "UPDATE commands SET created_at = ?2 WHERE id = ?1",
+                    params![id, created_at],
+                )
+                .unwrap();
+        }
+
+        let ids = |filter: ListFilter| -> Vec<String> {
+            let mut ids: Vec<String> = db
+                .list_commands(&filter, SortOrder::Created)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        let text = |s: &str| Some(s.to_string());
+
+        assert_eq!(
+            ids(ListFilter {
+                directory: text("/srv/app"),
+                ..Default::default()
+            }),
+            ["id1"]
+        );
+        assert_eq!(
+            ids(ListFilter {
+                under: text("/srv/app/"),
+                ..Default::default()
+            }),
+            ["id1", "id2"]
+        );
+        assert_eq!(
+            ids(ListFilter {
+                under: text("/"),
+                ..Default::default()
+            })
+            .len(),
+            4
+        );
+        assert_eq!(
+            ids(ListFilter {
+                program: text("git"),
+                ..Default::default()
+            }),
+            ["id1", "id2"]
+        );
+        // 2024-03-10T12:00:00+02:00 is 10:00 UTC, before id4
+        assert_eq!(
+            ids(ListFilter {
+                since: text("2024-02-10T12:00:00+00:00"),
+                until: text("2024-03-10T10:30:00Z"),
+                ..Default::default()
+            }),
+            ["id2", "id3"]
+        );
+    }
+
+    #[test]
+    fn test_alphabetical_sort_and_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "cargo test");
+        insert(&db, "id2", "Docker ps");
+        insert(&db, "id3", "awk '{print $1}'");
+        insert(&db, "id4", "cargo build");
+
+        let filter = ListFilter::default();
+        let cmds = |page: Page| -> Vec<String> {
+            db.list_page(&filter, SortOrder::Alphabetical, page)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.cmd)
+                .collect()
+        };
+
+        assert_eq!(
+            cmds(Page::default()),
+            ["awk '{print $1}'", "cargo build", "cargo test", "Docker ps"]
+        );
+        assert_eq!(
+            cmds(Page {
+                limit: Some(2),
+                offset: 1,
+            }),
+            ["cargo build", "cargo test"]
+        );
+        assert_eq!(
+            cmds(Page {
+                limit: None,
+                offset: 3,
+            }),
+            ["Docker ps"]
+        );
+        assert!(cmds(Page {
+            limit: Some(5),
+            offset: 10,
+        })
+        .is_empty());
+
+        assert_eq!(db.count_commands(&filter).unwrap(), 4);
+        let filter = ListFilter {
+            program: Some("cargo".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(db.count_commands(&filter).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_tags_removed_with_command() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "cargo build");
+        db.add_tags("id1", &["rust".to_string()]).unwrap();
+        db.delete_command("id1").unwrap();
+
+        assert!(db.list_tags().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_labels_rejected() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "cargo build");
+        assert!(db.add_tags("id1", &["two words".to_string()]).is_err());
+        assert!(db.set_group("id1", Some("")).is_err());
+        assert!(db.add_tags("missing", &["rust".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_record_use_and_sort() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "cargo build");
+        insert(&db, "id2", "cargo test");
+        insert(&db, "id3", "cargo clippy");
+
+        db.record_use("id2").unwrap();
+        db.record_use("id2").unwrap();
+        db.record_use("id3").unwrap();
+        assert!(db.record_use("missing").is_err());
+
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::UseCount)
+            .unwrap();
+        let ids: Vec<&str> = commands.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids[..2], ["id2", "id3"]);
+        assert_eq!(commands[0].use_count, 2);
+        assert!(commands[0].last_used_at.is_some());
+
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::LastUsed)
+            .unwrap();
+        let ids: Vec<&str> = commands.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["id3", "id2", "id1"]);
+        assert_eq!(commands[2].use_count, 0);
+        assert_eq!(commands[2].last_used_at, None);
+    }
+
+    #[test]
+    fn test_set_description() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "jq '.items[] | .name'");
+
+        #[cfg(not(feature = "embeddings"))]
+        db.set_description("id1", Some("Print item names")).unwrap();
+        #[cfg(feature = "embeddings")]
+        db.set_description("id1", Some("Print item names"), &[0.2f32; 384])
+            .unwrap();
+
+        let command = db.get_command("id1").unwrap().unwrap();
+        assert_eq!(command.description.as_deref(), Some("Print item names"));
+        assert!(db.get_command("missing").unwrap().is_none());
+
+        #[cfg(not(feature = "embeddings"))]
+        assert!(db.set_description("missing", None).is_err());
+        #[cfg(feature = "embeddings")]
+        assert!(db.set_description("missing", None, &[0.2f32; 384]).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "embeddings"))]
+    fn test_search_matches_description() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        db.insert_command("id1", "awk '{print $2}'", Some("Second column"), None)
+            .unwrap();
+        db.insert_command("id2", "ls -la", None, None).unwrap();
+
+        let results = db
+            .search_commands(Query::Text("column"), 10, &ListFilter::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command.id, "id1");
+    }
+
+    #[test]
+    fn test_savepoint_rollback_and_commit() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        let outer = db.savepoint().unwrap();
+        insert(&db, "id1", "cargo build");
+        {
+            let _inner = db.savepoint().unwrap();
+            insert(&db, "id2", "cargo test");
+            // Dropped without commit: only id2 is rolled back
+        }
+        outer.commit().unwrap();
+
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].id, "id1");
+
+        let savepoint = db.savepoint().unwrap();
+        db.delete_command("id1").unwrap();
+        savepoint.rollback().unwrap();
+        assert!(db.get_command("id1").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_dedup_per_directory() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        assert_eq!(
+            save_in(&db, "id1", "make test", "/repo/a"),
+            SaveOutcome::Inserted("id1".to_string())
+        );
+        db.add_tags("id1", &["ci".to_string()]).unwrap();
+        assert_eq!(
+            save_in(&db, "id2", "make test", "/repo/b"),
+            SaveOutcome::Inserted("id2".to_string())
+        );
+        assert_eq!(
+            save_in(&db, "id3", "make test", "/repo/a"),
+            SaveOutcome::Updated("id1".to_string())
+        );
+
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
+        assert_eq!(commands.len(), 2);
+        let first = db.get_command("id1").unwrap().unwrap();
+        assert_eq!(first.tags, vec!["ci"]);
+    }
+
+    #[test]
+    fn test_dedup_global_updates_directory() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path())
+            .unwrap()
+            .with_dedup_policy(DedupPolicy::Global);
+
+        save_in(&db, "id1", "make test", "/repo/a");
+        assert_eq!(
+            save_in(&db, "id2", "make test", "/repo/b"),
+            SaveOutcome::Updated("id1".to_string())
+        );
+
+        let command = db.get_command("id1").unwrap().unwrap();
+        assert_eq!(command.working_directory.as_deref(), Some("/repo/b"));
+        assert!(db.get_command("id2").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_dedup_keep_all() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path())
+            .unwrap()
+            .with_dedup_policy(DedupPolicy::KeepAll);
+
+        save_in(&db, "id1", "make test", "/repo/a");
+        save_in(&db, "id2", "make test", "/repo/a");
+
+        let commands = db
+            .list_commands(&ListFilter::default(), SortOrder::Created)
+            .unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(
+            "keep-all".parse::<DedupPolicy>().unwrap(),
+            DedupPolicy::KeepAll
+        );
+        assert!("sometimes".parse::<DedupPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_repair_dangling_references() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "cargo build");
+        db.add_tags("id1", &["rust".to_string()]).unwrap();
+        db.set_group("id1", Some("komando")).unwrap();
+        assert!(db.check_consistency().unwrap().is_consistent());
+
+        // Simulate writes made by a connection without foreign key enforcement
+        db.conn.pragma_update(None, "foreign_keys", false).unwrap();
+        db.conn
+            .execute("DELETE FROM commands WHERE id = 'id1'", [])
+            .unwrap();
+        insert(&db, "id2", "cargo test");
+        db.conn
+            .execute("UPDATE commands SET group_id = 42 WHERE id = 'id2'", [])
+            .unwrap();
+        db.conn.pragma_update(None, "foreign_keys", true).unwrap();
+
+        let report = db.check_consistency().unwrap();
+        assert_eq!(report.dangling_references.len(), 2);
+
+        #[cfg(not(feature = "embeddings"))]
+        db.repair(&report).unwrap();
+        #[cfg(feature = "embeddings")]
+        db.repair(&report, &mut |_| Ok(vec![0.1f32; 384])).unwrap();
+
+        assert!(db.check_consistency().unwrap().is_consistent());
+        assert!(db.list_tags().unwrap().is_empty());
+        assert_eq!(db.get_command("id2").unwrap().unwrap().group, None);
+    }
+
+    #[test]
+    #[cfg(feature = "embeddings")]
+    fn test_repair_embeddings() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "cargo build");
+        insert(&db, "id2", "cargo test");
+        db.conn
+            .execute("DELETE FROM cmd_embeddings WHERE cmd_id = 'id1'", [])
+            .unwrap();
+        db.conn
+            .execute("DELETE FROM commands WHERE id = 'id2'", [])
+            .unwrap();
+
+        let report = db.check_consistency().unwrap();
+        assert_eq!(report.missing_embeddings, vec!["id1"]);
+        assert_eq!(report.orphaned_embeddings, vec!["id2"]);
+
+        let mut embedded = Vec::new();
+        db.repair(&report, &mut |text| {
+            embedded.push(text.to_string());
+            Ok(vec![0.2f32; 384])
+        })
+        .unwrap();
+
+        assert_eq!(embedded, vec!["cargo build"]);
+        assert!(db.check_consistency().unwrap().is_consistent());
+    }
+
+    #[test]
+    #[cfg(feature = "embeddings")]
+    fn test_failed_insert_leaves_no_rows() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        // Wrong dimension: the embedding insert fails after the command row was written
+        assert!(db
+            .insert_command("id1", "cargo build", None, None, &[0.1f32; 3])
+            .is_err());
+
+        assert!(db.get_command("id1").unwrap().is_none());
+        assert!(db.check_consistency().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("push origin").unwrap(), "\"push\"* \"origin\"*");
+        assert_eq!(
+            fts_query("docker-compose up").unwrap(),
+            "\"docker compose\"* \"up\"*"
+        );
+        assert_eq!(fts_query("./run.sh").unwrap(), "\"run sh\"*");
+        assert_eq!(fts_query("\"quoted\"").unwrap(), "\"quoted\"*");
+        assert_eq!(fts_query(" | && "), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "embeddings"))]
+    fn test_fulltext_search() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        db.insert_command("id1", "git push origin main", None, None)
+            .unwrap();
+        db.insert_command("id2", "docker-compose up -d", None, None)
+            .unwrap();
+        db.insert_command(
+            "id3",
+            "make deploy",
+            Some("push the release to origin"),
+            None,
+        )
+        .unwrap();
+        db.insert_command("id4", "kubectl rollout restart", None, None)
+            .unwrap();
+        db.add_tags("id4", &["k8s".to_string()]).unwrap();
+
+        let search = |query: &str| -> Vec<String> {
+            db.search_commands(Query::Text(query), 10, &ListFilter::default())
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command.id)
+                .collect()
+        };
+
+        // Word order does not matter, and command text outranks the description
+        assert_eq!(search("origin push"), vec!["id1", "id3"]);
+        // Prefixes match
+        assert_eq!(search("roll"), vec!["id4"]);
+        assert_eq!(search("compose"), vec!["id2"]);
+        // Punctuation is tokenized the same way as the index
+        assert_eq!(search("docker-compose"), vec!["id2"]);
+        // Tags are indexed
+        assert_eq!(search("k8s"), vec!["id4"]);
+        // Queries without tokens fall back to substring matching
+        assert_eq!(search("-"), vec!["id2"]);
+        assert!(search("nothing").is_empty());
+    }
+
+    #[test]
+    #[cfg(not(feature = "embeddings"))]
+    fn test_fulltext_index_follows_changes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        db.insert_command("id1", "jq '.items[]'", None, None)
+            .unwrap();
+        let search = |query: &str| {
+            db.search_commands(Query::Text(query), 10, &ListFilter::default())
+                .unwrap()
+                .len()
+        };
+
+        db.set_description("id1", Some("iterate items")).unwrap();
+        assert_eq!(search("iterate"), 1);
+        db.set_description("id1", None).unwrap();
+        assert_eq!(search("iterate"), 0);
+
+        db.add_tags("id1", &["json".to_string()]).unwrap();
+        assert_eq!(search("json"), 1);
+        db.remove_tags("id1", &["json".to_string()]).unwrap();
+        assert_eq!(search("json"), 0);
+
+        db.delete_command("id1").unwrap();
+        assert_eq!(search("items"), 0);
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "docker ps -a");
+        insert(&db, "id2", "git push origin main");
+        insert(&db, "id3", "docker compose ps");
+        db.add_tags("id3", &["compose".to_string()]).unwrap();
+
+        let results = db
+            .search_commands(Query::Fuzzy("dkrps"), 10, &ListFilter::default())
+            .unwrap();
+        let ids: Vec<&str> = results.iter().map(|r| r.command.id.as_str()).collect();
+        assert_eq!(ids, vec!["id1", "id3"]);
+        assert_eq!(results[0].positions, vec![0, 3, 5, 7, 8]);
+        assert!(results[0].score > results[1].score);
+
+        let filter = ListFilter {
+            tags: vec!["compose".to_string()],
+            ..Default::default()
+        };
+        let results = db
+            .search_commands(Query::Fuzzy("dkrps"), 10, &filter)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command.id, "id3");
+
+        let results = db
+            .search_commands(Query::Fuzzy("dkrps"), 1, &ListFilter::default())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(db
+            .search_commands(Query::Fuzzy("zz"), 10, &ListFilter::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "embeddings")]
+    fn test_hybrid_search_keeps_literal_matches_on_top() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        // Embeddings pointing in different directions: the query is closest to id2 and id3
+        let embedding = |x: f32| {
+            let mut embedding = vec![0.0f32; 384];
+            embedding[0] = x;
+            embedding[1] = 1.0 - x;
+            embedding
+        };
+        db.insert_command(
+            "id1",
+            "kubectl rollout restart",
+            None,
+            None,
+            &embedding(0.0),
+        )
+        .unwrap();
+        db.insert_command("id2", "docker restart web", None, None, &embedding(0.9))
+            .unwrap();
+        db.insert_command(
+            "id3",
+            "systemctl restart nginx",
+            None,
+            None,
+            &embedding(0.8),
+        )
+        .unwrap();
+        let query = embedding(1.0);
+
+        let semantic = db
+            .search_commands(Query::Semantic(&query), 3, &ListFilter::default())
+            .unwrap();
+        let ids: Vec<&str> = semantic.iter().map(|r| r.command.id.as_str()).collect();
+        assert_eq!(ids, vec!["id2", "id3", "id1"]);
+        assert!(semantic.iter().all(|r| r.distance.is_some()));
+
+        let hybrid = db
+            .search_commands(
+                Query::Hybrid("kubectl rollout", &query),
+                3,
+                &ListFilter::default(),
+            )
+            .unwrap();
+        let ids: Vec<&str> = hybrid.iter().map(|r| r.command.id.as_str()).collect();
+        assert_eq!(ids, vec!["id1", "id2", "id3"]);
+        assert_eq!(hybrid[0].distance, semantic[2].distance);
+        assert!((hybrid[0].score - (1.0 / 61.0 + 1.0 / 63.0)).abs() < 1e-9);
+        assert!((hybrid[1].score - 1.0 / 61.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_update_command() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "id1", "git psuh");
+        db.add_tags("id1", &["old".to_string()]).unwrap();
+        db.record_use("id1").unwrap();
+
+        let fields = CommandFields {
+            cmd: "git push".to_string(),
+            description: Some("Push".to_string()),
+            working_directory: Some("/repo".to_string()),
+            group: Some("git".to_string()),
+            tags: vec!["new".to_string(), "vcs".to_string()],
+        };
+        #[cfg(feature = "embeddings")]
+        db.update_command("id1", &fields, &[0.3f32; 384]).unwrap();
+        #[cfg(not(feature = "embeddings"))]
+        db.update_command("id1", &fields).unwrap();
+
+        let command = db.get_command("id1").unwrap().unwrap();
+        assert_eq!(command.fields(), fields);
+        assert_eq!(command.use_count, 1);
+        assert_eq!(
+            db.search_commands(Query::Text("push"), 10, &ListFilter::default())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Invalid fields leave the command untouched
+        let invalid = CommandFields {
+            tags: vec!["bad tag".to_string()],
+            ..fields.clone()
+        };
+        #[cfg(feature = "embeddings")]
+        let result = db.update_command("id1", &invalid, &[0.3f32; 384]);
+        #[cfg(not(feature = "embeddings"))]
+        let result = db.update_command("id1", &invalid);
+        assert!(result.is_err());
+        assert_eq!(db.get_command("id1").unwrap().unwrap().fields(), fields);
+    }
+
+    #[test]
+    fn test_resolve_id() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        insert(&db, "ab12", "ls");
+        insert(&db, "ab34", "pwd");
+        insert(&db, "cd56", "whoami");
+
+        assert_eq!(db.resolve_id("c").unwrap(), "cd56");
+        assert_eq!(db.resolve_id("ab3").unwrap(), "ab34");
+        assert_eq!(db.resolve_id("ab12").unwrap(), "ab12");
+
+        let err = db.resolve_id("ab").unwrap_err().to_string();
+        assert!(err.contains("ambiguous"));
+        assert!(err.contains("ab12  ls") && err.contains("ab34  pwd"));
+
+        assert!(db
+            .resolve_id("ef")
+            .unwrap_err()
+            .to_string()
+            .contains("No command"));
+        assert!(db.resolve_id("%").is_err());
+        assert!(db.resolve_id(" ").is_err());
+    }
 }
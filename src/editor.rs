@@ -0,0 +1,229 @@
+//! Editing a saved command as a text document in the user's editor.
+//!
+//! The document starts with `field: value` header lines, followed by a blank line
+//! and the command itself, which may span several lines:
+//!
+//! ```text
+//! description: Push the current branch
+//! directory: /home/me/project
+//! tags: git, deploy
+//! group:
+//!
+//! git push origin HEAD
+//! ```
+
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::db::{self, CommandFields, SavedCommand};
+
+const HELP: &str = "\
+# Edit the fields and the command below, then save and close the editor.
+# Lines starting with '#' before the command are ignored. Empty fields are cleared,
+# tags are separated by commas. Delete the command to cancel.
+";
+
+/// Renders the editable fields of a command as a document.
+pub fn render(command: &SavedCommand) -> String {
+    format!(
+        "{HELP}description: {}\ndirectory: {}\ntags: {}\ngroup: {}\n\n{}\n",
+        command.description.as_deref().unwrap_or(""),
+        command.working_directory.as_deref().unwrap_or(""),
+        command.tags.join(", "),
+        command.group.as_deref().unwrap_or(""),
+        command.cmd,
+    )
+}
+
+/// Parses a document produced by [`render`], returning `None` if the command was removed.
+pub fn parse(document: &str) -> Result<Option<CommandFields>> {
+    let mut fields = CommandFields {
+        cmd: String::new(),
+        description: None,
+        working_directory: None,
+        group: None,
+        tags: Vec::new(),
+    };
+
+    let mut lines = document.lines();
+    for (number, line) in lines.by_ref().enumerate() {
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            bail!(
+                "Line {}: expected 'field: value' or a blank line before the command, found '{}'",
+                number + 1,
+                line
+            );
+        };
+        let value = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        let line_context = || format!("Line {}", number + 1);
+        match key.trim().to_lowercase().as_str() {
+            "description" => fields.description = value,
+            "directory" => fields.working_directory = value,
+            "group" => {
+                fields.group = value
+                    .map(|group| db::normalize_label("Group", &group))
+                    .transpose()
+                    .with_context(line_context)?
+            }
+            "tags" => {
+                fields.tags = value
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|tag| !tag.trim().is_empty())
+                    .map(|tag| db::normalize_label("Tag", tag))
+                    .collect::<Result<_>>()
+                    .with_context(line_context)?
+            }
+            other => bail!(
+                "Line {}: unknown field '{}', expected description, directory, tags or group",
+                number + 1,
+                other
+            ),
+        }
+    }
+
+    fields.cmd = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+    if fields.cmd.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(fields))
+}
+
+/// Opens the command in `$VISUAL` or `$EDITOR` (falling back to `vi`) until the result
+/// parses, returning the edited fields, or `None` if the user cancelled.
+pub fn edit(command: &SavedCommand) -> Result<Option<CommandFields>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // A new file only we can read, rather than one at a predictable path that may
+    // already exist, e.g. as a symlink
+    let mut file = tempfile::Builder::new()
+        .prefix("komando-edit-")
+        .suffix(".txt")
+        .tempfile()
+        .context("Failed to create a temporary file")?;
+    file.write_all(render(command).as_bytes())?;
+    file.flush()?;
+    let path = file.path().to_path_buf();
+
+    loop {
+        // Through the shell, so that EDITOR may carry arguments (e.g. "code --wait")
+        let mut editor_command = Command::new("sh");
+        editor_command
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(&path);
+        // The shell integration captures stdout, so terminal editors get the terminal itself
+        if let Ok(tty) = File::options().read(true).write(true).open("/dev/tty") {
+            editor_command
+                .stdin(Stdio::from(tty.try_clone()?))
+                .stdout(Stdio::from(tty));
+        }
+        let status = editor_command
+            .status()
+            .with_context(|| format!("Failed to run editor '{}'", editor))?;
+        if !status.success() {
+            bail!("Editor '{}' exited with {}", editor, status);
+        }
+
+        // Read by path: editors often replace the file rather than write to it
+        let document = fs::read_to_string(&path)?;
+        match parse(&document) {
+            Ok(fields) => return Ok(fields),
+            Err(e) => {
+                eprint!(">>> Error: {:#}. Edit again? (Y/n): ", e);
+                std::io::stderr().flush()?;
+                let mut input = String::new();
+                std::io::stdin().read_line(&mut input)?;
+                if input.trim().eq_ignore_ascii_case("n") {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_command() -> SavedCommand {
+        SavedCommand {
+            id: "id1".to_string(),
+            cmd: "kubectl get pods \\\n  -o wide".to_string(),
+            description: Some("List pods: all of them".to_string()),
+            working_directory: Some("/srv".to_string()),
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            updated_at: None,
+            group: None,
+            tags: vec!["k8s".to_string(), "ops".to_string()],
+            use_count: 0,
+            last_used_at: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let command = saved_command();
+        let fields = parse(&render(&command)).unwrap().unwrap();
+        assert_eq!(fields, command.fields());
+    }
+
+    #[test]
+    fn test_parse_edits() {
+        let document = "\
+# comment
+Description:  New text
+tags: a,, b ,c
+group: tools
+
+# Not a header comment: part of the command
+echo hi
+";
+        let fields = parse(document).unwrap().unwrap();
+        assert_eq!(fields.description.as_deref(), Some("New text"));
+        assert_eq!(fields.working_directory, None);
+        assert_eq!(fields.tags, vec!["a", "b", "c"]);
+        assert_eq!(fields.group.as_deref(), Some("tools"));
+        assert_eq!(
+            fields.cmd,
+            "# Not a header comment: part of the command\necho hi"
+        );
+    }
+
+    #[test]
+    fn test_empty_command_cancels() {
+        assert_eq!(parse("description: x\n\n   \n").unwrap(), None);
+        assert_eq!(parse("").unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let err = parse("owner: me\n\nls").unwrap_err();
+        assert!(err.to_string().contains("unknown field 'owner'"));
+
+        let err = parse("description: x\nls -la\n").unwrap_err();
+        assert!(err.to_string().contains("Line 2"));
+    }
+
+    #[test]
+    fn test_invalid_labels() {
+        let err = parse("tags: ok, two words\n\nls").unwrap_err();
+        assert_eq!(err.to_string(), "Line 1");
+        assert!(format!("{:#}", err).contains("Invalid tag name 'two words'"));
+
+        let err = parse("description: x\ngroup: a b\n\nls").unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 2: Invalid group name 'a b'"));
+    }
+}
@@ -0,0 +1,202 @@
+//! fzf-style fuzzy matching.
+//!
+//! A pattern matches a text if its characters appear in the text in the same order,
+//! possibly with gaps (`dkrps` matches `docker ps -a`). Among all possible alignments
+//! the best scoring one is chosen: matches at word boundaries and runs of consecutive
+//! matches score higher, gaps cost a little.
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = 3;
+const GAP_EXTENSION: i64 = 1;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL_CASE: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Quality of the match, higher is better.
+    pub score: i64,
+    /// Character (not byte) indices of the matched characters in the text, ascending.
+    pub positions: Vec<usize>,
+}
+
+/// Matches `pattern` against `text`, returning `None` if it does not match.
+///
+/// Whitespace in the pattern is ignored. Matching is case-insensitive unless the
+/// pattern contains an uppercase letter ("smart case"). An empty pattern matches
+/// everything with a score of 0.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let normalize = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+
+    let pattern: Vec<char> = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(normalize)
+        .collect();
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().copied().map(normalize).collect();
+
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+
+    // Cheap rejection before the quadratic scoring pass
+    let mut remaining = pattern.iter().peekable();
+    for c in &text {
+        if remaining.peek() == Some(&c) {
+            remaining.next();
+        }
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+
+    let bonuses: Vec<i64> = (0..original.len())
+        .map(|j| bonus(j.checked_sub(1).map(|k| original[k]), original[j]))
+        .collect();
+
+    // scores[i][j]: best score of matching pattern[..=i] with pattern[i] at text[j].
+    // from[i][j]: position of pattern[i - 1] in that alignment.
+    let (m, n) = (pattern.len(), text.len());
+    let mut scores = vec![vec![None; n]; m];
+    let mut from = vec![vec![0; n]; m];
+
+    for (i, &p) in pattern.iter().enumerate() {
+        // Best score of pattern[..i] ending strictly before j - 1, including the gap penalty up to j
+        let mut gapped: Option<(i64, usize)> = None;
+
+        for j in 0..n {
+            if i > 0 && j >= 2 {
+                let extended = gapped.map(|(score, k)| (score - GAP_EXTENSION, k));
+                let opened = scores[i - 1][j - 2].map(|score: i64| (score - GAP_START, j - 2));
+                gapped = match (extended, opened) {
+                    (Some(a), Some(b)) => Some(if b.0 >= a.0 { b } else { a }),
+                    (a, b) => a.or(b),
+                };
+            }
+
+            if text[j] != p {
+                continue;
+            }
+
+            if i == 0 {
+                scores[i][j] = Some(SCORE_MATCH + bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER);
+                continue;
+            }
+
+            let consecutive = j
+                .checked_sub(1)
+                .and_then(|k| scores[i - 1][k].map(|score| (score + BONUS_CONSECUTIVE, k)));
+            let best = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+
+            if let Some((score, k)) = best {
+                scores[i][j] = Some(score + SCORE_MATCH + bonuses[j]);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, score) = scores[m - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        // Prefer the earliest end position among equal scores
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))?;
+
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Bonus for matching `current`, depending on the character before it.
+fn bonus(previous: Option<char>, current: char) -> i64 {
+    match previous {
+        None => BONUS_BOUNDARY,
+        Some(p) if !p.is_alphanumeric() && current.is_alphanumeric() => BONUS_BOUNDARY,
+        Some(p) if p.is_lowercase() && current.is_uppercase() => BONUS_CAMEL_CASE,
+        Some(p) if !p.is_ascii_digit() && current.is_ascii_digit() => BONUS_CAMEL_CASE,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_abbreviation() {
+        let m = fuzzy_match("dkrps", "docker ps -a").unwrap();
+        assert_eq!(m.positions, vec![0, 3, 5, 7, 8]);
+    }
+
+    #[test]
+    fn test_no_match() {
+        assert_eq!(fuzzy_match("psd", "docker ps -a"), None);
+        assert_eq!(fuzzy_match("xyz", ""), None);
+    }
+
+    #[test]
+    fn test_empty_pattern_matches() {
+        let m = fuzzy_match("", "ls").unwrap();
+        assert_eq!(m.score, 0);
+        assert!(m.positions.is_empty());
+    }
+
+    #[test]
+    fn test_prefers_word_boundaries() {
+        // Both letters of "gp" start a word of "git push"
+        let m = fuzzy_match("gp", "git push").unwrap();
+        assert_eq!(m.positions, vec![0, 4]);
+
+        // "lg" could match the "g" at the end of "log", but "graph" starts a word
+        let m = fuzzy_match("lg", "git log --graph").unwrap();
+        assert_eq!(m.positions, vec![4, 10]);
+    }
+
+    #[test]
+    fn test_scores_rank_better_matches_higher() {
+        let score = |pattern, text| fuzzy_match(pattern, text).unwrap().score;
+
+        // Consecutive beats scattered
+        assert!(score("push", "git push") > score("push", "pip uninstall sh"));
+        // Boundary beats middle of a word
+        assert!(score("ps", "docker ps") > score("ps", "gitops sync"));
+    }
+
+    #[test]
+    fn test_smart_case() {
+        assert!(fuzzy_match("ls", "LS -la").is_some());
+        assert!(fuzzy_match("LS", "ls -la").is_none());
+        assert!(fuzzy_match("LS", "LS -la").is_some());
+    }
+
+    #[test]
+    fn test_positions_are_char_indices() {
+        let m = fuzzy_match("cf", "écho café").unwrap();
+        assert_eq!(m.positions, vec![5, 7]);
+    }
+
+    #[test]
+    fn test_whitespace_in_pattern_is_ignored() {
+        let m = fuzzy_match("git st", "git status").unwrap();
+        assert_eq!(m.positions, vec![0, 1, 2, 4, 5]);
+    }
+}
@@ -0,0 +1,436 @@
+//! Key bindings of the interactive picker.
+//!
+//! Keys are written as in the config file: a key name (`enter`, `esc`, `tab`, `space`,
+//! `up`, `down`, ...) or a single character, optionally prefixed by `ctrl-` or `alt-`.
+//! Several keys for one action are separated by commas (`up,ctrl-p`), and keys pressed
+//! one after the other by spaces (`g g`).
+//!
+//! A [`Preset`] gives the defaults of every key, and makes the picker modal like vim.
+
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a key does in the picker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickerAction {
+    Up,
+    Down,
+    /// Move the selection by a screen
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    /// Mark or unmark the selected item, when several may be picked
+    Mark,
+    /// Select the Nth item, counting from 1
+    Jump(usize),
+    /// Type into the query, in a modal keymap
+    Search,
+    /// Show or hide the preview of the selected item
+    Preview,
+    /// Delete the selected search result, after confirmation
+    Delete,
+    /// Edit the command text, tags or description of the selected search result
+    Edit,
+    Tag,
+    Describe,
+    /// Copy the selected command to the clipboard
+    Copy,
+    Accept,
+    /// Pick the selected search result to print it rather than execute it
+    Print,
+    Cancel,
+}
+
+/// A key with its modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 15] = [
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("space", KeyCode::Char(' ')),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+impl Key {
+    /// Whether a key press from the terminal is this key. Shift is ignored for characters,
+    /// which already carry it in their case.
+    fn matches(&self, event: &KeyEvent) -> bool {
+        let mut modifiers = event.modifiers;
+        if matches!(event.code, KeyCode::Char(_)) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        self.code == event.code && self.modifiers == modifiers
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut name = s.trim();
+        loop {
+            if let Some(rest) = name.strip_prefix("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                name = rest;
+            } else if let Some(rest) = name.strip_prefix("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                name = rest;
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match NAMED_KEYS.iter().find(|(n, _)| *n == name) {
+                Some(&(_, code)) => code,
+                None => bail!(
+                    "Unknown key '{}', expected a character or one of: {}, \
+                     optionally prefixed by ctrl- or alt-",
+                    s,
+                    NAMED_KEYS.map(|(n, _)| n).join(", ")
+                ),
+            },
+        };
+
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            KeyCode::BackTab => write!(f, "Shift-Tab"),
+            KeyCode::Delete => write!(f, "Del"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Keys pressed one after the other.
+pub type Sequence = Vec<Key>;
+
+/// Parses a comma-separated list of key sequences.
+pub fn parse_keys(s: &str) -> Result<Vec<Sequence>> {
+    let sequences = s
+        .split(',')
+        .filter(|sequence| !sequence.trim().is_empty())
+        .map(|sequence| sequence.split_whitespace().map(Key::from_str).collect())
+        .collect::<Result<Vec<_>>>()?;
+    if sequences.is_empty() {
+        bail!("Expected at least one key");
+    }
+    Ok(sequences)
+}
+
+/// What the keys pressed so far do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Action(PickerAction),
+    /// They start a longer sequence: wait for the next key
+    Pending,
+    None,
+}
+
+/// Which keys trigger which picker actions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(Sequence, PickerAction)>,
+    /// Keys are actions until the query is focused, and Esc leaves it, as in vim
+    pub modal: bool,
+    /// Whether clicks select items and the wheel scrolls
+    pub mouse: bool,
+}
+
+impl Keymap {
+    /// Binds `sequences` to `action`, on top of the existing bindings.
+    pub fn bind(&mut self, action: PickerAction, sequences: &[Sequence]) {
+        self.bindings
+            .extend(sequences.iter().map(|sequence| (sequence.clone(), action)));
+    }
+
+    /// What the key presses so far trigger. Earlier bindings win.
+    pub fn lookup(&self, events: &[KeyEvent]) -> Lookup {
+        let starts_with = |sequence: &Sequence| {
+            sequence.len() >= events.len()
+                && sequence
+                    .iter()
+                    .zip(events)
+                    .all(|(key, event)| key.matches(event))
+        };
+        let mut lookup = Lookup::None;
+        for (sequence, action) in &self.bindings {
+            if starts_with(sequence) {
+                if sequence.len() == events.len() {
+                    return Lookup::Action(*action);
+                }
+                lookup = Lookup::Pending;
+            }
+        }
+        lookup
+    }
+
+    /// The action bound to a single key press, if any.
+    pub fn action(&self, event: &KeyEvent) -> Option<PickerAction> {
+        match self.lookup(std::slice::from_ref(event)) {
+            Lookup::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    /// The keys bound to `action`, for help texts (e.g. "Space/Tab").
+    pub fn describe(&self, action: PickerAction) -> String {
+        self.bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(sequence, _)| {
+                sequence
+                    .iter()
+                    .map(Key::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// A set of default keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preset {
+    /// Arrows and the usual editing keys
+    #[default]
+    Default,
+    /// Ctrl-N/Ctrl-P and other emacs movements on top of the defaults
+    Emacs,
+    /// j/k, gg/G and friends; the query is focused with `/`
+    Vim,
+}
+
+impl Preset {
+    pub const NAMES: [&'static str; 3] = ["default", "emacs", "vim"];
+
+    /// The default keys of the `keys.<name>` setting in this preset, if it changes them.
+    pub fn keys(self, name: &str) -> Option<&'static str> {
+        let keys = match (self, name) {
+            (Preset::Default, _) => return None,
+            (Preset::Emacs, "up") => "up,ctrl-p",
+            (Preset::Emacs, "down") => "down,ctrl-n",
+            (Preset::Emacs, "page_up") => "pageup,alt-v",
+            (Preset::Emacs, "page_down") => "pagedown,ctrl-v",
+            (Preset::Emacs, "first") => "home,alt-<",
+            (Preset::Emacs, "last") => "end,alt->",
+            (Preset::Emacs, "cancel") => "esc,ctrl-g,ctrl-d",
+            (Preset::Vim, "up") => "up,k,ctrl-p",
+            (Preset::Vim, "down") => "down,j,ctrl-n",
+            (Preset::Vim, "page_up") => "pageup,ctrl-b",
+            (Preset::Vim, "page_down") => "pagedown,ctrl-f",
+            (Preset::Vim, "first") => "home,g g",
+            (Preset::Vim, "last") => "end,G",
+            (Preset::Vim, "delete") => "delete,d d",
+            (Preset::Vim, "copy") => "ctrl-y,y y",
+            // Ctrl-D closes the picker in every preset, rather than paging as in vim
+            (Preset::Vim, "cancel") => "esc,q,ctrl-d",
+            _ => return None,
+        };
+        Some(keys)
+    }
+
+    /// Sets up what is not configured key by key: number keys and modes.
+    pub fn apply(self, keymap: &mut Keymap) {
+        keymap.modal = self == Preset::Vim;
+        // Alt-1 to Alt-9 jump to the numbered items, as do plain digits outside of the
+        // query in vim
+        for n in 1..=9 {
+            let digit = KeyCode::Char(char::from_digit(n, 10).unwrap());
+            let mut sequences = vec![vec![Key {
+                code: digit,
+                modifiers: KeyModifiers::ALT,
+            }]];
+            if keymap.modal {
+                sequences.push(vec![Key {
+                    code: digit,
+                    modifiers: KeyModifiers::NONE,
+                }]);
+            }
+            keymap.bind(PickerAction::Jump(n as usize), &sequences);
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(Preset::Default),
+            "emacs" => Ok(Preset::Emacs),
+            "vim" => Ok(Preset::Vim),
+            _ => bail!(
+                "Unknown key preset '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keys: Vec<Key> = parse_keys("up, ctrl-p,k,pagedown")
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(keys.len(), 4);
+        assert!(keys[0].matches(&press(KeyCode::Up, KeyModifiers::NONE)));
+        assert!(keys[1].matches(&press(KeyCode::Char('p'), KeyModifiers::CONTROL)));
+        assert!(!keys[1].matches(&press(KeyCode::Char('p'), KeyModifiers::NONE)));
+        assert!(keys[2].matches(&press(KeyCode::Char('k'), KeyModifiers::NONE)));
+        assert!(keys[3].matches(&press(KeyCode::PageDown, KeyModifiers::NONE)));
+
+        let sequences = parse_keys("home, g g").unwrap();
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences[1].len(), 2);
+
+        assert!(parse_keys("").is_err());
+        assert!(parse_keys("ctrl-").is_err());
+        assert!(parse_keys("hyper-x").is_err());
+    }
+
+    #[test]
+    fn test_shift_is_part_of_characters() {
+        let key: Key = "G".parse().unwrap();
+        assert!(key.matches(&press(KeyCode::Char('G'), KeyModifiers::SHIFT)));
+        assert!(!key.matches(&press(KeyCode::Char('g'), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn test_keymap() {
+        let mut keymap = Keymap::default();
+        keymap.bind(PickerAction::Mark, &parse_keys("space,tab").unwrap());
+        keymap.bind(PickerAction::Accept, &parse_keys("enter,tab").unwrap());
+
+        assert_eq!(
+            keymap.action(&press(KeyCode::Tab, KeyModifiers::NONE)),
+            Some(PickerAction::Mark)
+        );
+        assert_eq!(
+            keymap.action(&press(KeyCode::Enter, KeyModifiers::NONE)),
+            Some(PickerAction::Accept)
+        );
+        assert_eq!(
+            keymap.action(&press(KeyCode::Esc, KeyModifiers::NONE)),
+            None
+        );
+        assert_eq!(keymap.describe(PickerAction::Mark), "Space/Tab");
+        assert_eq!(keymap.describe(PickerAction::Accept), "Enter/Tab");
+    }
+
+    #[test]
+    fn test_sequences() {
+        let mut keymap = Keymap::default();
+        keymap.bind(PickerAction::First, &parse_keys("home,g g").unwrap());
+        keymap.bind(PickerAction::Last, &parse_keys("G").unwrap());
+        let g = press(KeyCode::Char('g'), KeyModifiers::NONE);
+
+        assert_eq!(keymap.lookup(&[g]), Lookup::Pending);
+        assert_eq!(keymap.lookup(&[g, g]), Lookup::Action(PickerAction::First));
+        assert_eq!(
+            keymap.lookup(&[press(KeyCode::Char('G'), KeyModifiers::SHIFT)]),
+            Lookup::Action(PickerAction::Last)
+        );
+        assert_eq!(
+            keymap.lookup(&[g, press(KeyCode::Char('x'), KeyModifiers::NONE)]),
+            Lookup::None
+        );
+        // A prefix alone is not an action
+        assert_eq!(keymap.action(&g), None);
+        assert_eq!(keymap.describe(PickerAction::First), "Home/g g");
+    }
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(Preset::Default.keys("up"), None);
+        assert_eq!(Preset::Emacs.keys("down"), Some("down,ctrl-n"));
+        assert_eq!(Preset::Vim.keys("first"), Some("home,g g"));
+        assert_eq!(Preset::Vim.keys("accept"), None);
+        for preset in Preset::NAMES {
+            let preset: Preset = preset.parse().unwrap();
+            for name in [
+                "up",
+                "down",
+                "page_up",
+                "page_down",
+                "first",
+                "last",
+                "delete",
+                "copy",
+                "cancel",
+            ] {
+                if let Some(keys) = preset.keys(name) {
+                    assert!(parse_keys(keys).is_ok(), "{:?} {}", preset, name);
+                }
+            }
+        }
+        assert!("nano".parse::<Preset>().is_err());
+
+        let mut keymap = Keymap::default();
+        Preset::Emacs.apply(&mut keymap);
+        assert!(!keymap.modal);
+        assert_eq!(
+            keymap.action(&press(KeyCode::Char('3'), KeyModifiers::ALT)),
+            Some(PickerAction::Jump(3))
+        );
+        assert_eq!(
+            keymap.action(&press(KeyCode::Char('3'), KeyModifiers::NONE)),
+            None
+        );
+
+        let mut keymap = Keymap::default();
+        Preset::Vim.apply(&mut keymap);
+        assert!(keymap.modal);
+        assert_eq!(
+            keymap.action(&press(KeyCode::Char('9'), KeyModifiers::NONE)),
+            Some(PickerAction::Jump(9))
+        );
+    }
+}
@@ -1,25 +1,35 @@
 use anyhow::Result;
-use clap::{Arg, Command as ClapCommand};
-use crossterm::{
-    cursor::{Hide, MoveTo, Show},
-    event::{self, Event, KeyCode},
-    execute, queue,
-    style::Print,
-    terminal::{self, Clear, ClearType},
-};
 use dirs::home_dir;
 use std::env;
 use std::fs;
-use std::io::Write;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+mod cli;
+mod config;
 mod db;
+mod editor;
+mod fuzzy;
+mod keymap;
+mod migrations;
 mod ops;
-
-use db::Db;
-use ops::CommandStore;
+mod output;
+mod picker;
+
+use cli::{Action, ConfigAction, SaveOptions};
+use config::{Config, ConfigFile};
+use db::{
+    CommandFields, ConsistencyReport, Db, ListFilter, Page, Query, SaveOutcome, SavedCommand,
+    SearchMode, SearchResult,
+};
+use keymap::PickerAction;
+use ops::{CommandStore, MigrationReport};
+use output::Record;
+use picker::{Catalog, Choice, Edit, PickerItem};
 
 #[cfg(feature = "embeddings")]
-use db::Embedder;
+use db::{embedding_text, Embedder};
 
 fn get_last_commands(count: usize) -> Vec<String> {
     let file_content = fs::read_to_string("/tmp/last_commands.txt").ok();
@@ -51,180 +61,655 @@ fn get_last_commands(count: usize) -> Vec<String> {
         .collect()
 }
 
-fn main() -> Result<()> {
-    // println!("Debug: Received arguments: {:?}", std::env::args().collect::<Vec<_>>());
-
-    let matches = ClapCommand::new("Komando")
-        .version("0.1.0")
-        .author("Noureddine Gueddach")
-        .about("A command line utility to better organize and keep track of your commands.")
-        .arg(
-            Arg::new("save")
-                .short('s')
-                .long("save")
-                .help("Save the last command to a file")
-                .action(clap::ArgAction::SetTrue),
-        )
-        .arg(
-            Arg::new("query")
-                .short('q')
-                .long("query")
-                .value_name("QUERY")
-                .help("Search for a command")
-                .num_args(1),
-        )
-        .arg(
-            Arg::new("list")
-                .short('l')
-                .long("list")
-                .help("List all saved commands")
-                .action(clap::ArgAction::SetTrue),
-        )
-        .arg(
-            Arg::new("delete")
-                .short('d')
-                .long("delete")
-                .value_name("ID")
-                .help("Delete a command by ID")
-                .num_args(1),
-        )
-        .arg(
-            Arg::new("clear")
-                .long("clear")
-                .help("Delete all saved commands")
-                .action(clap::ArgAction::SetTrue),
-        )
-        .arg(
-            Arg::new("count")
-                .short('n')
-                .long("number")
-                .value_name("COUNT")
-                .help("Number of commands to show (default: 5)")
-                .value_parser(clap::value_parser!(usize))
-                .default_value("5"),
-        )
-        .arg(
-            Arg::new("init")
-                .long("init")
-                .help("Initialize shell integration")
-                .action(clap::ArgAction::SetTrue),
-        )
-        .get_matches();
-
-    // Check if we should default to save behavior (no other main action specified)
-    let is_default_save = !matches.get_flag("save")
-        && !matches.get_flag("list")
-        && !matches.get_flag("init")
-        && !matches.get_flag("clear")
-        && matches.get_one::<String>("delete").is_none()
-        && matches.get_one::<String>("query").is_none();
-
-    let count = matches.get_one::<usize>("count").copied().unwrap_or(5);
-    let last_commands = get_last_commands(count);
-
-    if let Some(home_path) = home_dir() {
-        if matches.get_flag("init") {
-            println!("alias komando='komando_exec $@'");
-            return Ok(());
+/// Loads a legacy JSON store and imports it into the database.
+fn migrate_legacy_store(
+    db: &Db,
+    path: &Path,
+    dry_run: bool,
+    #[cfg(feature = "embeddings")] embedder: &mut Embedder,
+) -> Result<MigrationReport> {
+    let store = CommandStore::load(&path.to_path_buf())?;
+
+    #[cfg(feature = "embeddings")]
+    let report = ops::migrate(&store, db, dry_run, &mut |text| embedder.embed(text));
+    #[cfg(not(feature = "embeddings"))]
+    let report = ops::migrate(&store, db, dry_run);
+
+    report
+}
+
+fn print_consistency_report(report: &ConsistencyReport) {
+    for (table, rowid) in &report.dangling_references {
+        println!(
+            "Dangling reference: row {} of '{}' points to missing data",
+            rowid, table
+        );
+    }
+    #[cfg(feature = "embeddings")]
+    {
+        for id in &report.orphaned_embeddings {
+            println!("Orphaned embedding: command {} no longer exists", id);
+        }
+        for id in &report.missing_embeddings {
+            println!(
+                "Missing embedding: command {} is invisible to semantic search",
+                id
+            );
+        }
+    }
+}
+
+/// Prompts for a description on stderr, keeping stdout free for the shell integration.
+/// Returns `None` if the user enters nothing.
+fn read_description(message: &str) -> Result<Option<String>> {
+    eprint!("{}: ", message);
+    std::io::stderr().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+    Ok(Some(input.to_string()).filter(|d| !d.is_empty()))
+}
+
+/// Every stored field of a command as "Field: value" lines, including empty ones.
+fn details(command: &SavedCommand) -> Vec<String> {
+    let or_none = |value: Option<&str>| value.unwrap_or("(none)").to_string();
+    let tags = if command.tags.is_empty() {
+        "(none)".to_string()
+    } else {
+        command.tags.join(", ")
+    };
+
+    vec![
+        format!("Command: {}", command.cmd),
+        format!("ID: {}", command.id),
+        format!("Description: {}", or_none(command.description.as_deref())),
+        format!(
+            "Directory: {}",
+            or_none(command.working_directory.as_deref())
+        ),
+        format!("Group: {}", or_none(command.group.as_deref())),
+        format!("Tags: {}", tags),
+        format!("Saved: {}", command.created_at),
+        format!("Updated: {}", or_none(command.updated_at.as_deref())),
+        format!("Uses: {}", command.use_count),
+        format!("Last used: {}", or_none(command.last_used_at.as_deref())),
+    ]
+}
+
+/// Prints every stored field of a command, including empty ones.
+fn print_details(command: &SavedCommand) {
+    for line in details(command) {
+        println!("{}", line);
+    }
+}
+
+/// Saves a command, computing its embedding and asking for a description if requested.
+/// Errors are reported to the user rather than returned.
+fn save_command(
+    db: &Db,
+    cmd: &str,
+    working_dir: &str,
+    options: &SaveOptions,
+    #[cfg(feature = "embeddings")] embedder: &mut Embedder,
+) -> Result<()> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let description = match options.description.as_deref() {
+        Some("") => read_description(&format!("Description for '{}'", cmd))?,
+        Some(text) => Some(text.to_string()),
+        None => None,
+    };
+
+    #[cfg(feature = "embeddings")]
+    let result = embedder
+        .embed(&embedding_text(cmd, description.as_deref()))
+        .map_err(|e| e.context("Error generating embedding"))
+        .and_then(|embedding| {
+            db.insert_command(
+                &id,
+                cmd,
+                description.as_deref(),
+                Some(working_dir),
+                &embedding,
+            )
+        });
+    #[cfg(not(feature = "embeddings"))]
+    let result = db.insert_command(&id, cmd, description.as_deref(), Some(working_dir));
+
+    match result {
+        Ok(outcome) => report_saved(
+            db,
+            &outcome,
+            cmd,
+            working_dir,
+            &options.tags,
+            options.group.as_deref(),
+        ),
+        Err(e) => {
+            eprintln!(">>> Error saving command: {:#}", e);
+            Ok(())
         }
+    }
+}
+
+/// Labels a freshly saved command and tells the user whether it was new.
+fn report_saved(
+    db: &Db,
+    outcome: &SaveOutcome,
+    cmd: &str,
+    working_dir: &str,
+    tags: &[String],
+    group: Option<&str>,
+) -> Result<()> {
+    apply_labels(db, outcome.id(), tags, group)?;
+    match outcome {
+        SaveOutcome::Inserted(_) => println!(">>> Saved command: {} at {}", cmd, working_dir),
+        SaveOutcome::Updated(id) => println!(
+            ">>> Command already saved (ID: {}), refreshed: {} at {}",
+            id, cmd, working_dir
+        ),
+    }
+    Ok(())
+}
+
+/// Resolves `dir` against the current directory, following symlinks if it exists.
+fn absolute_dir(dir: &Path) -> Result<PathBuf> {
+    let dir = env::current_dir()?.join(dir);
+    // Keep directories that do not exist on this machine as given
+    Ok(fs::canonicalize(&dir).unwrap_or(dir))
+}
 
-        let db_path = home_path.join(".komando.db");
-        let json_path = home_path.join(".komando.json");
+/// Assigns tags and, if given, a group to a saved command.
+fn apply_labels(db: &Db, id: &str, tags: &[String], group: Option<&str>) -> Result<()> {
+    db.add_tags(id, tags)?;
+    if let Some(group) = group {
+        db.set_group(id, Some(group))?;
+    }
+    Ok(())
+}
 
-        let db = Db::new(&db_path)?;
+/// Shows or changes the settings.
+fn run_config(action: ConfigAction) -> Result<()> {
+    let mut file = ConfigFile::open()?;
+    let setting = |key: &str| config::setting(key).expect("keys are validated by the CLI");
+
+    match action {
+        ConfigAction::List => {
+            let width = config::SETTINGS
+                .iter()
+                .map(|s| s.key.len())
+                .max()
+                .unwrap_or(0);
+            for setting in &config::SETTINGS {
+                let (value, source) = file.value(setting)?;
+                println!("{:width$} = {}  # {}", setting.key, value, source);
+            }
+        }
+        ConfigAction::Get { key } => println!("{}", file.value(setting(&key))?.0),
+        ConfigAction::Set { key, value } => {
+            let setting = setting(&key);
+            file.set(setting, &value)?;
+            file.save()?;
+            println!(">>> Set {} = {} in {}", key, value, file.path().display());
+            if let (_, config::Source::Env(name)) = file.value(setting)? {
+                eprintln!(">>> Note: {} is set and overrides the config file", name);
+            }
+        }
+        ConfigAction::Path => println!("{}", file.path().display()),
+    }
+    Ok(())
+}
 
-        // Migration logic
+/// How long the live search waits for the next key before embedding the query.
+#[cfg(feature = "embeddings")]
+const EMBEDDING_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Searches in the given mode. An empty query lists the most used commands.
+fn run_search(
+    db: &Db,
+    query: &str,
+    mode: SearchMode,
+    limit: usize,
+    filter: &ListFilter,
+    #[cfg(feature = "embeddings")] embedder: &mut Embedder,
+) -> Result<Vec<SearchResult>> {
+    if query.trim().is_empty() {
+        return db.search_commands(Query::Fuzzy(""), limit, filter);
+    }
+    match mode {
+        SearchMode::Text => db.search_commands(Query::Text(query), limit, filter),
+        SearchMode::Fuzzy => db.search_commands(Query::Fuzzy(query), limit, filter),
         #[cfg(feature = "embeddings")]
-        let mut embedder = Embedder::new()?;
+        SearchMode::Semantic => {
+            let query_embedding = embedder.embed(query)?;
+            db.search_commands(Query::Semantic(&query_embedding), limit, filter)
+        }
+        #[cfg(feature = "embeddings")]
+        SearchMode::Hybrid => {
+            let query_embedding = embedder.embed(query)?;
+            db.search_commands(Query::Hybrid(query, &query_embedding), limit, filter)
+        }
+    }
+}
 
-        if json_path.exists() {
-            if let Ok(store) = CommandStore::load(&json_path) {
-                println!("Migrating commands from JSON to SQLite...");
-                for cmd in store.list_all() {
-                    #[cfg(feature = "embeddings")]
-                    {
-                        if let Ok(embedding) = embedder.embed(&cmd.command) {
-                            let _ = db.insert_command(
-                                cmd.get_id(),
-                                &cmd.command,
-                                None,
-                                Some(&cmd.working_directory),
-                                &embedding,
-                            );
+/// The saved commands offered by the search picker, which can also be changed from it.
+struct CommandCatalog<'a> {
+    db: &'a Db,
+    mode: SearchMode,
+    limit: usize,
+    filter: &'a ListFilter,
+    show_scores: bool,
+    #[cfg(feature = "embeddings")]
+    embedder: &'a mut Embedder,
+    /// Results of the last search, in the order shown
+    results: Vec<SearchResult>,
+}
+
+impl Catalog for CommandCatalog<'_> {
+    fn search(&mut self, query: &str) -> Result<Vec<PickerItem>> {
+        self.results = run_search(
+            self.db,
+            query,
+            self.mode,
+            self.limit,
+            self.filter,
+            #[cfg(feature = "embeddings")]
+            self.embedder,
+        )?;
+        Ok(self
+            .results
+            .iter()
+            .map(|result| {
+                let command = &result.command;
+                let mut preview = details(command);
+                preview.push(format!("Score: {:.4}", result.score));
+                if let Some(distance) = result.distance {
+                    preview.push(format!("Distance: {:.4}", distance));
+                }
+                PickerItem {
+                    text: command.cmd.clone(),
+                    positions: result.positions.clone(),
+                    detail: self.show_scores.then(|| match result.distance {
+                        Some(distance) => {
+                            format!("[score {:.4}, distance {:.4}]", result.score, distance)
                         }
-                    }
-                    #[cfg(not(feature = "embeddings"))]
-                    {
-                        let _ = db.insert_command(
-                            cmd.get_id(),
-                            &cmd.command,
-                            None,
-                            Some(&cmd.working_directory),
-                        );
-                    }
+                        None => format!("[score {:.4}]", result.score),
+                    }),
+                    preview,
+                    tags: command.tags.clone(),
+                    description: command.description.clone(),
                 }
-                // Rename the old file so we don't migrate again
-                let _ = fs::rename(&json_path, home_path.join(".komando.json.bak"));
-                println!("Migration complete.");
+            })
+            .collect())
+    }
+
+    fn edit(&mut self, index: usize, edit: Edit) -> Result<()> {
+        let command = &self.results[index].command;
+        let mut fields = command.fields();
+        match edit {
+            Edit::Delete => return self.db.delete_command(&command.id),
+            Edit::Command(cmd) => fields.cmd = cmd,
+            Edit::Tags(tags) => fields.tags = tags,
+            Edit::Description(description) => fields.description = description,
+        }
+        update_command(
+            self.db,
+            &command.id,
+            &fields,
+            #[cfg(feature = "embeddings")]
+            self.embedder,
+        )
+    }
+}
+
+/// Replaces the editable fields of a command, recomputing its embedding.
+fn update_command(
+    db: &Db,
+    id: &str,
+    fields: &CommandFields,
+    #[cfg(feature = "embeddings")] embedder: &mut Embedder,
+) -> Result<()> {
+    #[cfg(feature = "embeddings")]
+    return embedder
+        .embed(&embedding_text(&fields.cmd, fields.description.as_deref()))
+        .and_then(|embedding| db.update_command(id, fields, &embedding));
+    #[cfg(not(feature = "embeddings"))]
+    db.update_command(id, fields)
+}
+
+fn main() -> Result<()> {
+    let mut action = cli::parse()?;
+
+    if action == Action::Init {
+        println!("alias komando='komando_exec $@'");
+        return Ok(());
+    }
+
+    if let Action::Config(config_action) = action {
+        return run_config(config_action);
+    }
+    let config = Config::load()?;
+
+    let Some(home_path) = home_dir() else {
+        println!("Could not determine home directory.");
+        return Ok(());
+    };
+
+    let json_path = home_path.join(".komando.json");
+    let backup_path = home_path.join(".komando.json.bak");
+
+    let dedup = match &action {
+        Action::Save(args) => args.options.dedup,
+        Action::Add(args) => args.options.dedup,
+        _ => None,
+    };
+    if let Some(dir) = config.db_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let db = Db::new(&config.db_path)?.with_dedup_policy(dedup.unwrap_or(config.dedup));
+
+    #[cfg(feature = "embeddings")]
+    let mut embedder = Embedder::new()?;
+
+    // Migrate the legacy JSON store automatically the first time it is found.
+    // The report goes to stderr so that it cannot be mistaken for picker output.
+    let is_maintenance = matches!(action, Action::Migrate { .. } | Action::Check { .. });
+    if !is_maintenance && json_path.exists() {
+        eprintln!("Migrating commands from JSON to SQLite...");
+
+        #[cfg(feature = "embeddings")]
+        let result = migrate_legacy_store(&db, &json_path, false, &mut embedder);
+        #[cfg(not(feature = "embeddings"))]
+        let result = migrate_legacy_store(&db, &json_path, false);
+
+        // Back the store up even on failure so the automatic migration runs only once
+        fs::rename(&json_path, &backup_path)?;
+        match result {
+            Ok(report) => {
+                report.print(&mut std::io::stderr())?;
+                if report.has_failures() {
+                    eprintln!(
+                        "Some commands could not be migrated. Retry with: komando migrate {}",
+                        backup_path.display()
+                    );
+                }
+            }
+            Err(e) => eprintln!(
+                ">>> Error migrating legacy store: {}. Retry with: komando migrate {}",
+                e,
+                backup_path.display()
+            ),
+        }
+    }
+
+    if let Some(id) = action.id_mut() {
+        match db.resolve_id(id) {
+            Ok(full_id) => *id = full_id,
+            Err(e) => {
+                eprintln!(">>> Error: {}", e);
+                return Ok(());
             }
         }
+    }
+
+    // Working directories are stored absolute, so compare against absolute ones too
+    if let Some(filter) = action.filter_mut() {
+        for dir in [&mut filter.directory, &mut filter.under]
+            .into_iter()
+            .flatten()
+        {
+            *dir = absolute_dir(Path::new(dir))?.to_string_lossy().into_owned();
+        }
+    }
+
+    match action {
+        Action::Init | Action::Config(_) => unreachable!("handled before opening the database"),
+        Action::Migrate { file, dry_run } => {
+            let path = file.unwrap_or_else(|| json_path.clone());
 
-        let current_dir = env::current_dir()?;
+            if !path.exists() {
+                println!("No legacy command store found at {}", path.display());
+                return Ok(());
+            }
 
-        if matches.get_flag("save") || is_default_save {
-            // Get the last command:
-            let last_command = last_commands.first();
-            if let Some(last_command) = last_command {
-                let working_dir = current_dir.to_str().unwrap();
-                let id = uuid::Uuid::new_v4().to_string();
+            #[cfg(feature = "embeddings")]
+            let report = migrate_legacy_store(&db, &path, dry_run, &mut embedder)?;
+            #[cfg(not(feature = "embeddings"))]
+            let report = migrate_legacy_store(&db, &path, dry_run)?;
+
+            report.print(&mut std::io::stdout())?;
+            if !dry_run && path == json_path {
+                fs::rename(&json_path, &backup_path)?;
+                println!("Legacy store backed up to {}", backup_path.display());
+            }
+        }
+        Action::Check { repair } => {
+            let report = db.check_consistency()?;
+            print_consistency_report(&report);
 
+            if report.is_consistent() {
+                println!("Database is consistent.");
+            } else if repair {
                 #[cfg(feature = "embeddings")]
-                {
-                    match embedder.embed(last_command) {
-                        Ok(embedding) => {
-                            match db.insert_command(
-                                &id,
-                                last_command,
-                                None,
-                                Some(working_dir),
-                                &embedding,
-                            ) {
-                                Ok(_) => println!(
-                                    ">>> Saved command: {} at {}",
-                                    last_command, working_dir
-                                ),
-                                Err(e) => eprintln!(">>> Error saving command: {}", e),
-                            }
-                        }
-                        Err(e) => eprintln!(">>> Error generating embedding: {}", e),
-                    }
-                }
+                db.repair(&report, &mut |text| embedder.embed(text))?;
                 #[cfg(not(feature = "embeddings"))]
-                {
-                    match db.insert_command(&id, last_command, None, Some(working_dir)) {
-                        Ok(_) => println!(">>> Saved command: {} at {}", last_command, working_dir),
-                        Err(e) => eprintln!(">>> Error saving command: {}", e),
-                    }
-                }
+                db.repair(&report)?;
+                println!(">>> Database repaired");
             } else {
+                println!("Run 'komando check --repair' to fix these problems.");
+            }
+        }
+        Action::Save(args) => {
+            let last_commands = get_last_commands(args.count.unwrap_or(1));
+            if last_commands.is_empty() {
                 eprintln!(">>> Error: No last command found to save. Please ensure /tmp/last_commands.txt contains valid command history.");
+                return Ok(());
+            }
+
+            // With an explicit count, let the user pick which of the recent commands to save
+            let picked = if args.count.is_some() && last_commands.len() > 1 {
+                let items: Vec<PickerItem> = last_commands.iter().map(PickerItem::new).collect();
+                let keys = &config.keymap;
+                let help = format!(
+                    "Press '{}' to mark commands, '{}' to save them, '{}' to exit",
+                    keys.describe(PickerAction::Mark),
+                    keys.describe(PickerAction::Accept),
+                    keys.describe(PickerAction::Cancel)
+                );
+                let height = args.height.unwrap_or(config.height);
+                picker::pick(&items, true, height, &help, keys)?.unwrap_or_default()
+            } else {
+                vec![0]
+            };
+
+            let current_dir = env::current_dir()?;
+            for index in picked {
+                save_command(
+                    &db,
+                    &last_commands[index],
+                    &current_dir.to_string_lossy(),
+                    &args.options,
+                    #[cfg(feature = "embeddings")]
+                    &mut embedder,
+                )?;
+            }
+        }
+        Action::Add(args) => {
+            let command = match args.command {
+                Some(command) => command,
+                None if std::io::stdin().is_terminal() => {
+                    eprintln!(">>> Error: No command given. Pass it after '--' or on stdin.");
+                    return Ok(());
+                }
+                None => {
+                    let mut input = String::new();
+                    std::io::stdin().read_to_string(&mut input)?;
+                    input
+                }
+            };
+            let command = command.trim();
+            if command.is_empty() {
+                eprintln!(">>> Error: Cannot save an empty command");
+                return Ok(());
+            }
+
+            let working_dir = match args.directory {
+                Some(dir) => absolute_dir(&dir)?,
+                None => env::current_dir()?,
+            };
+
+            save_command(
+                &db,
+                command,
+                &working_dir.to_string_lossy(),
+                &args.options,
+                #[cfg(feature = "embeddings")]
+                &mut embedder,
+            )?;
+        }
+        Action::List(args) => {
+            let commands =
+                db.list_page(&args.filter, args.sort.unwrap_or(config.sort), args.page)?;
+
+            if let Some(format) = args.format {
+                let records: Vec<Record> = commands.iter().map(Record::from_command).collect();
+                output::write_records(&mut std::io::stdout().lock(), format, &records)?;
+                return Ok(());
             }
-            return Ok(());
-        } else if matches.get_flag("list") {
-            let commands = db.get_all_commands()?;
 
             if commands.is_empty() {
                 println!("No saved commands found.");
             } else {
                 println!("\n=== Saved Commands ===");
-                for (id, cmd) in &commands {
-                    println!("\nCommand: {}", cmd);
-                    println!("ID: {}", id);
+                for command in &commands {
+                    println!("\nCommand: {}", command.cmd);
+                    println!("ID: {}", command.id);
+                    if let Some(description) = &command.description {
+                        println!("Description: {}", description);
+                    }
+                    println!("Saved: {}", command.created_at);
+                    match (command.use_count, &command.last_used_at) {
+                        (0, _) => println!("Used: never"),
+                        (count, Some(last_used_at)) => {
+                            println!("Used: {} time(s), last at {}", count, last_used_at)
+                        }
+                        (count, None) => println!("Used: {} time(s)", count),
+                    }
+                    if let Some(group) = &command.group {
+                        println!("Group: {}", group);
+                    }
+                    if !command.tags.is_empty() {
+                        println!("Tags: {}", command.tags.join(", "));
+                    }
                 }
-                println!("\nTotal: {} command(s)\n", commands.len());
+                if args.page == Page::default() {
+                    println!("\nTotal: {} command(s)\n", commands.len());
+                } else {
+                    println!(
+                        "\nShowing {}-{} of {} command(s)\n",
+                        args.page.offset + 1,
+                        args.page.offset + commands.len(),
+                        db.count_commands(&args.filter)?
+                    );
+                }
+            }
+        }
+        Action::ListTags => {
+            let tags = db.list_tags()?;
+            let groups = db.list_groups()?;
+
+            if tags.is_empty() && groups.is_empty() {
+                println!("No tags or groups found.");
+            } else {
+                println!("\n=== Groups ===");
+                for (name, count) in &groups {
+                    println!("{} ({})", name, count);
+                }
+                println!("\n=== Tags ===");
+                for (name, count) in &tags {
+                    println!("{} ({})", name, count);
+                }
+                println!();
+            }
+        }
+        Action::Label(args) => {
+            let id = &args.id;
+            let result = apply_labels(&db, id, &args.tags, args.group.as_deref())
+                .and_then(|_| db.remove_tags(id, &args.untags))
+                .and_then(|_| {
+                    if args.ungroup {
+                        db.set_group(id, None)
+                    } else {
+                        Ok(())
+                    }
+                });
+            match result {
+                Ok(_) => println!(">>> Command updated successfully"),
+                Err(e) => eprintln!(">>> Error: {}", e),
             }
-            return Ok(());
-        } else if matches.get_flag("clear") {
+        }
+        Action::Describe { id, description } => {
+            let Some(command) = db.get_command(&id)? else {
+                eprintln!(">>> Error: No command found with ID '{}'", id);
+                return Ok(());
+            };
+            let description = match description {
+                Some(text) => Some(text).filter(|d| !d.is_empty()),
+                None => read_description(&format!("Description for '{}'", command.cmd))?,
+            };
+
+            #[cfg(feature = "embeddings")]
+            let result = embedder
+                .embed(&embedding_text(&command.cmd, description.as_deref()))
+                .and_then(|embedding| db.set_description(&id, description.as_deref(), &embedding));
+            #[cfg(not(feature = "embeddings"))]
+            let result = db.set_description(&id, description.as_deref());
+
+            match result {
+                Ok(_) => println!(">>> Description updated successfully"),
+                Err(e) => eprintln!(">>> Error: {}", e),
+            }
+        }
+        Action::Show { id, format } => {
+            let Some(command) = db.get_command(&id)? else {
+                eprintln!(">>> Error: No command found with ID '{}'", id);
+                return Ok(());
+            };
+            match format {
+                Some(format) => output::write_record(
+                    &mut std::io::stdout().lock(),
+                    format,
+                    &Record::from_command(&command),
+                )?,
+                None => print_details(&command),
+            }
+        }
+        Action::Edit { id } => {
+            let Some(command) = db.get_command(&id)? else {
+                eprintln!(">>> Error: No command found with ID '{}'", id);
+                return Ok(());
+            };
+            let Some(fields) = editor::edit(&command)? else {
+                eprintln!(">>> Edit cancelled, nothing was changed");
+                return Ok(());
+            };
+            if fields == command.fields() {
+                println!(">>> No changes");
+                return Ok(());
+            }
+
+            let result = update_command(
+                &db,
+                &id,
+                &fields,
+                #[cfg(feature = "embeddings")]
+                &mut embedder,
+            );
+            match result {
+                Ok(_) => println!(">>> Command updated successfully"),
+                Err(e) => eprintln!(">>> Error: {}", e),
+            }
+        }
+        Action::Clear => {
             // Confirm with user
             eprint!("Are you sure you want to delete all commands? (y/N): ");
             std::io::stderr().flush()?;
@@ -238,106 +723,89 @@ fn main() -> Result<()> {
             } else {
                 eprintln!(">>> Operation cancelled");
             }
-            return Ok(());
-        } else if let Some(id) = matches.get_one::<String>("delete") {
-            match db.delete_command(id) {
-                Ok(_) => println!(">>> Command deleted successfully"),
-                Err(e) => eprintln!(">>> Error: {}", e),
-            }
-            return Ok(());
-        } else if let Some(query) = matches.get_one::<String>("query") {
-            #[cfg(feature = "embeddings")]
-            let search_results = {
-                let query_embedding = embedder.embed(query)?;
-                db.search_commands(&query_embedding, 10)?
-                    .into_iter()
-                    .map(|(id, cmd, wd, _dist)| (id, cmd, wd))
-                    .collect::<Vec<_>>()
+        }
+        Action::Remove { id } => match db.delete_command(&id) {
+            Ok(_) => println!(">>> Command deleted successfully"),
+            Err(e) => eprintln!(">>> Error: {}", e),
+        },
+        Action::Search(args) => {
+            let mode = args.mode.unwrap_or(config.search_mode);
+            let mut catalog = CommandCatalog {
+                db: &db,
+                mode,
+                limit: config.search_limit,
+                filter: &args.filter,
+                show_scores: args.show_scores,
+                #[cfg(feature = "embeddings")]
+                embedder: &mut embedder,
+                results: Vec::new(),
             };
 
-            #[cfg(not(feature = "embeddings"))]
-            let search_results = db.search_commands(query, 10)?;
-
-            if search_results.is_empty() {
-                println!("No commands found matching '{}'", query);
+            if let Some(format) = args.format {
+                catalog.search(&args.query)?;
+                let records: Vec<Record> =
+                    catalog.results.iter().map(Record::from_result).collect();
+                output::write_records(&mut std::io::stdout().lock(), format, &records)?;
                 return Ok(());
             }
 
-            // Interactive selection
-            terminal::enable_raw_mode()?;
-            // Use stderr for UI so stdout can be used for the result
-            let mut output = std::io::stderr();
-            let mut selected = 0;
-
-            loop {
-                // Clear screen and reset cursor
-                queue!(output, Clear(ClearType::All), MoveTo(0, 0), Hide)?;
-
-                // Display commands
-                for (i, (_, cmd, _)) in search_results.iter().enumerate() {
-                    let prefix = if i == selected { "> " } else { "  " };
-                    let number = format!("{}. ", i + 1);
-
-                    queue!(
-                        output,
-                        MoveTo(0, i as u16),
-                        Clear(ClearType::CurrentLine),
-                        Print(prefix),
-                        Print(number),
-                        Print(cmd.as_str()),
-                    )?;
-                }
+            let keys = &config.keymap;
+            // Keys only reach the query in a modal keymap after asking for it
+            let search_help = if keys.modal {
+                format!("{} search, ", keys.describe(PickerAction::Search))
+            } else {
+                String::new()
+            };
+            let help = format!(
+                "{}{} run, {} print, {} delete, {} edit, {} tags, {} description, {} copy, \
+                 {} preview, {} exit",
+                search_help,
+                keys.describe(PickerAction::Accept),
+                keys.describe(PickerAction::Print),
+                keys.describe(PickerAction::Delete),
+                keys.describe(PickerAction::Edit),
+                keys.describe(PickerAction::Tag),
+                keys.describe(PickerAction::Describe),
+                keys.describe(PickerAction::Copy),
+                keys.describe(PickerAction::Preview),
+                keys.describe(PickerAction::Cancel)
+            );
+            // Computing an embedding per key press would make typing lag
+            let debounce = match mode {
+                SearchMode::Text | SearchMode::Fuzzy => Duration::ZERO,
+                #[cfg(feature = "embeddings")]
+                SearchMode::Semantic | SearchMode::Hybrid => EMBEDDING_DEBOUNCE,
+            };
 
-                queue!(
-                    output,
-                    MoveTo(0, search_results.len() as u16),
-                    Print("Press 'Enter' to execute the selected command, 'Esc' to exit"),
-                    Print("\n"),
-                )?;
+            let picked = picker::search(
+                &args.query,
+                debounce,
+                args.height.unwrap_or(config.height),
+                config.preview,
+                &help,
+                keys,
+                &mut catalog,
+            )?;
+            let (index, print) = match picked {
+                Some(Choice::Execute(index)) => (index, false),
+                Some(Choice::Print(index)) => (index, true),
+                None => return Ok(()),
+            };
+            let command = &catalog.results[index].command;
 
-                output.flush()?;
+            // Usage statistics are best-effort and must not prevent running the command
+            let _ = db.record_use(&command.id);
 
-                if let Event::Key(key_event) = event::read()? {
-                    match key_event.code {
-                        KeyCode::Up => {
-                            selected = selected.saturating_sub(1);
-                        }
-                        KeyCode::Down => {
-                            if selected < search_results.len() - 1 {
-                                selected += 1;
-                            }
-                        }
-                        KeyCode::Enter => {
-                            let (_, cmd_text, wd) = &search_results[selected];
-                            let dir = wd.as_deref().unwrap_or(".");
-
-                            // Output directory and command separated by semicolon to stdout with prefix
-                            // This format is parsed by the shell function
-                            print!("KOMANDO_EXEC:{};{}", dir, cmd_text);
-                            std::io::stdout().flush()?;
-                            break;
-                        }
-                        KeyCode::Esc => {
-                            queue!(
-                                output,
-                                MoveTo(0, (search_results.len() + 1) as u16),
-                                Clear(ClearType::CurrentLine),
-                            )?;
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
+            if print {
+                println!("{}", command.cmd);
+                return Ok(());
             }
-
-            // Disable raw mode and show cursor
-            terminal::disable_raw_mode()?;
-            execute!(output, Show)?;
+            let dir = command.working_directory.as_deref().unwrap_or(".");
+            // Output directory and command separated by semicolon to stdout with prefix
+            // This format is parsed by the shell function
+            print!("KOMANDO_EXEC:{};{}", dir, command.cmd);
+            std::io::stdout().flush()?;
         }
-    } else {
-        println!("Could not determine home directory.");
-        // Exit early if we can't determine the home directory
-        return Ok(());
     }
 
     Ok(())
@@ -0,0 +1,262 @@
+//! Versioned schema migrations for the SQLite store.
+//!
+//! The schema version is tracked with `PRAGMA user_version`. Each entry in
+//! [`MIGRATIONS`] upgrades the database by exactly one version and is applied
+//! in its own transaction, so a failure leaves the database at the last
+//! successfully applied version.
+
+use anyhow::{bail, Context, Result};
+use rusqlite::Connection;
+
+/// Ordered list of schema upgrades. Entry `i` migrates from version `i` to `i + 1`.
+///
+/// Never edit or reorder an existing entry once released: append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // v1: initial schema. Uses IF NOT EXISTS so databases created before
+    // versioning was introduced (user_version = 0) are adopted as-is.
+    "CREATE TABLE IF NOT EXISTS commands (
+        id TEXT PRIMARY KEY,
+        cmd TEXT NOT NULL,
+        description TEXT,
+        working_directory TEXT,
+        created_at TEXT NOT NULL
+    );",
+    // v2: tags (many per command) and groups (at most one per command)
+    "CREATE TABLE groups (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE tags (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE command_tags (
+        command_id TEXT NOT NULL REFERENCES commands(id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (command_id, tag_id)
+    );
+    ALTER TABLE commands ADD COLUMN group_id INTEGER REFERENCES groups(id) ON DELETE SET NULL;",
+    // v3: usage tracking
+    "ALTER TABLE commands ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE commands ADD COLUMN last_used_at TEXT;",
+    // v4: time a command was last saved again, set when a duplicate is refreshed
+    "ALTER TABLE commands ADD COLUMN updated_at TEXT;
+    UPDATE commands SET updated_at = created_at;",
+    // v5: full-text index over command text, description and tags, kept in sync by triggers.
+    // Rows are keyed by the command id: the implicit rowid of `commands` is not an alias
+    // of any column, so VACUUM may renumber it.
+    // Only letters, digits and '_' are token characters, so shell punctuation
+    // (`-`, `/`, `.`, `|`, quotes, ...) separates tokens.
+    "CREATE VIRTUAL TABLE commands_fts USING fts5(
+        id UNINDEXED,
+        cmd,
+        description,
+        tags,
+        tokenize = \"unicode61 remove_diacritics 2 tokenchars '_'\",
+        prefix = '2 3'
+    );
+    INSERT INTO commands_fts (id, cmd, description, tags)
+        SELECT c.id, c.cmd, COALESCE(c.description, ''),
+            COALESCE((SELECT group_concat(t.name, ' ') FROM command_tags ct
+                      JOIN tags t ON t.id = ct.tag_id WHERE ct.command_id = c.id), '')
+        FROM commands c;
+    CREATE TRIGGER commands_fts_insert AFTER INSERT ON commands BEGIN
+        INSERT INTO commands_fts (id, cmd, description, tags)
+            VALUES (new.id, new.cmd, COALESCE(new.description, ''), '');
+    END;
+    CREATE TRIGGER commands_fts_delete AFTER DELETE ON commands BEGIN
+        DELETE FROM commands_fts WHERE id = old.id;
+    END;
+    CREATE TRIGGER commands_fts_update AFTER UPDATE OF id, cmd, description ON commands BEGIN
+        UPDATE commands_fts SET id = new.id, cmd = new.cmd, description = COALESCE(new.description, '')
+            WHERE id = old.id;
+    END;
+    CREATE TRIGGER command_tags_fts_insert AFTER INSERT ON command_tags BEGIN
+        UPDATE commands_fts SET tags = COALESCE((SELECT group_concat(t.name, ' ') FROM command_tags ct
+                JOIN tags t ON t.id = ct.tag_id WHERE ct.command_id = new.command_id), '')
+            WHERE id = new.command_id;
+    END;
+    CREATE TRIGGER command_tags_fts_delete AFTER DELETE ON command_tags BEGIN
+        UPDATE commands_fts SET tags = COALESCE((SELECT group_concat(t.name, ' ') FROM command_tags ct
+                JOIN tags t ON t.id = ct.tag_id WHERE ct.command_id = old.command_id), '')
+            WHERE id = old.command_id;
+    END;",
+];
+
+/// Schema version produced by running every migration known to this binary.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Returns the schema version currently recorded in the database.
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version)
+}
+
+/// Brings the database up to [`SCHEMA_VERSION`], applying pending migrations in order.
+///
+/// Refuses to touch a database whose version is newer than this binary knows about,
+/// since an older binary could otherwise corrupt data written by a newer one.
+pub fn run(conn: &mut Connection) -> Result<()> {
+    let version = current_version(conn)?;
+
+    if version > SCHEMA_VERSION {
+        bail!(
+            "Database schema version {} is newer than the latest version supported by this \
+             binary ({}). Please upgrade komando.",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let target = index as u32 + 1;
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)
+            .with_context(|| format!("Failed to apply schema migration to version {}", target))?;
+        tx.pragma_update(None, "user_version", target)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_migrates_fresh_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+
+        run(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), SCHEMA_VERSION);
+        conn.prepare("SELECT id, cmd, description, working_directory, created_at FROM commands")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_migration_is_idempotent() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+
+        run(&mut conn).unwrap();
+        run(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_adopts_unversioned_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+
+        // Schema as created by releases that predate versioning
+        conn.execute(
+            "CREATE TABLE commands (
+                id TEXT PRIMARY KEY,
+                cmd TEXT NOT NULL,
+                description TEXT,
+                working_directory TEXT,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO commands (id, cmd, created_at) VALUES ('id1', 'ls', '2024-01-01T00:00:00+00:00')",
+            [],
+        )
+        .unwrap();
+
+        run(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), SCHEMA_VERSION);
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM commands", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_fts_index_follows_commands() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+        run(&mut conn).unwrap();
+        let matches = |conn: &Connection, query: &str| -> Vec<String> {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT c.id FROM commands_fts JOIN commands c ON c.id = commands_fts.id
+                     WHERE commands_fts MATCH ?1 ORDER BY c.id",
+                )
+                .unwrap();
+            stmt.query_map([query], |row| row.get(0))
+                .unwrap()
+                .map(Result::unwrap)
+                .collect()
+        };
+
+        conn.execute_batch(
+            "INSERT INTO commands (id, cmd, created_at) VALUES ('a', 'git push', 'now');
+             INSERT INTO commands (id, cmd, created_at) VALUES ('b', 'git pull', 'now');
+             INSERT INTO tags (id, name) VALUES (1, 'deploy');
+             INSERT INTO command_tags (command_id, tag_id) VALUES ('b', 1);",
+        )
+        .unwrap();
+        assert_eq!(matches(&conn, "git"), ["a", "b"]);
+        assert_eq!(matches(&conn, "deploy"), ["b"]);
+
+        conn.execute_batch(
+            "UPDATE commands SET cmd = 'ls' WHERE id = 'a';
+             DELETE FROM command_tags;
+             DELETE FROM commands WHERE id = 'b';",
+        )
+        .unwrap();
+        assert_eq!(matches(&conn, "git"), Vec::<String>::new());
+        assert_eq!(matches(&conn, "ls"), ["a"]);
+        assert_eq!(matches(&conn, "deploy"), Vec::<String>::new());
+
+        // Rowids may change with VACUUM, the ids the index is keyed by do not
+        conn.execute_batch(
+            "INSERT INTO commands (id, cmd, created_at) VALUES ('c', 'cargo test', 'now');
+             DELETE FROM commands WHERE id = 'a';
+             INSERT INTO commands (id, cmd, created_at) VALUES ('d', 'cargo build', 'now');
+             VACUUM;",
+        )
+        .unwrap();
+        assert_eq!(matches(&conn, "cargo"), ["c", "d"]);
+        assert_eq!(matches(&conn, "test"), ["c"]);
+
+        // Search results find their command through its primary key, not by scanning
+        let mut stmt = conn
+            .prepare(
+                "EXPLAIN QUERY PLAN SELECT c.id FROM commands_fts
+                 JOIN commands c ON c.id = commands_fts.id WHERE commands_fts MATCH 'ls'",
+            )
+            .unwrap();
+        let plan: Vec<String> = stmt
+            .query_map([], |row| row.get(3))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert!(
+            plan.iter()
+                .any(|step| step.starts_with("SEARCH c USING") && step.ends_with("(id=?)")),
+            "{:?}",
+            plan
+        );
+    }
+
+    #[test]
+    fn test_refuses_newer_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut conn = Connection::open(temp_file.path()).unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+
+        let err = run(&mut conn).unwrap_err();
+        assert!(err.to_string().contains("newer"));
+    }
+}
@@ -8,8 +8,13 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 
+#[cfg(feature = "embeddings")]
+use crate::db::embedding_text;
+use crate::db::Db;
+
 /// Command structure from the legacy JSON format.
 /// All fields are preserved for deserialization compatibility with old JSON files.
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,6 +35,25 @@ impl Command {
     pub fn get_id(&self) -> &str {
         &self.id
     }
+
+    /// Group of the command, or `None` if it was left empty.
+    pub fn get_group(&self) -> Option<&str> {
+        Some(self.group.as_str()).filter(|g| !g.trim().is_empty())
+    }
+
+    pub fn get_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.tags.iter().cloned().collect();
+        tags.sort();
+        tags
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref().filter(|d| !d.trim().is_empty())
+    }
+
+    pub fn get_use_count(&self) -> u32 {
+        self.use_count
+    }
 }
 
 /// Legacy command store from JSON format.
@@ -69,6 +93,148 @@ impl CommandStore {
     }
 }
 
+/// What happened to a single legacy command during migration.
+#[derive(Debug, PartialEq)]
+pub enum ImportOutcome {
+    Imported,
+    Skipped(String),
+    Failed(String),
+}
+
+/// Per-command result of migrating a legacy store.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// `(id, command, outcome)` for every command in the legacy store, in store order
+    pub entries: Vec<(String, String, ImportOutcome)>,
+    pub dry_run: bool,
+}
+
+impl MigrationReport {
+    pub fn count(&self, matches: fn(&ImportOutcome) -> bool) -> usize {
+        self.entries.iter().filter(|(_, _, o)| matches(o)).count()
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.count(|o| matches!(o, ImportOutcome::Failed(_))) > 0
+    }
+
+    pub fn print(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for (id, command, outcome) in &self.entries {
+            match outcome {
+                ImportOutcome::Imported => writeln!(out, "  imported  {}  {}", id, command)?,
+                ImportOutcome::Skipped(reason) => {
+                    writeln!(out, "  skipped   {}  {} ({})", id, command, reason)?
+                }
+                ImportOutcome::Failed(error) => {
+                    writeln!(out, "  failed    {}  {} ({})", id, command, error)?
+                }
+            }
+        }
+        writeln!(
+            out,
+            "{}{} imported, {} skipped, {} failed",
+            if self.dry_run { "[dry run] " } else { "" },
+            self.count(|o| *o == ImportOutcome::Imported),
+            self.count(|o| matches!(o, ImportOutcome::Skipped(_))),
+            self.count(|o| matches!(o, ImportOutcome::Failed(_))),
+        )
+    }
+}
+
+/// Imports every command of a legacy store into the database, preserving its ID,
+/// directory, description, tags, group and use count.
+///
+/// The whole import runs in a single transaction. A command that fails to import is
+/// rolled back on its own and reported, without affecting the others. With `dry_run`,
+/// everything is rolled back at the end, so the report shows what would happen.
+/// Commands whose ID or text is already in the database are skipped, which makes
+/// re-running a migration safe.
+pub fn migrate(
+    store: &CommandStore,
+    db: &Db,
+    dry_run: bool,
+    #[cfg(feature = "embeddings")] embed: &mut dyn FnMut(&str) -> Result<Vec<f32>>,
+) -> Result<MigrationReport> {
+    let transaction = db.savepoint()?;
+    let mut report = MigrationReport {
+        entries: Vec::new(),
+        dry_run,
+    };
+
+    for cmd in store.list_all() {
+        let outcome = if cmd.command.trim().is_empty() {
+            ImportOutcome::Skipped("empty command".to_string())
+        } else if db.get_command(cmd.get_id())?.is_some() {
+            ImportOutcome::Skipped("already imported".to_string())
+        } else if let Some(existing) = db.find_duplicate(&cmd.command, working_directory(cmd))? {
+            ImportOutcome::Skipped(format!("already saved as {}", existing))
+        } else {
+            let savepoint = db.savepoint()?;
+            #[cfg(feature = "embeddings")]
+            let imported = import_command(cmd, db, embed);
+            #[cfg(not(feature = "embeddings"))]
+            let imported = import_command(cmd, db);
+            match imported {
+                Ok(()) => {
+                    savepoint.commit()?;
+                    ImportOutcome::Imported
+                }
+                Err(e) => {
+                    savepoint.rollback()?;
+                    ImportOutcome::Failed(e.to_string())
+                }
+            }
+        };
+        report
+            .entries
+            .push((cmd.get_id().to_string(), cmd.command.clone(), outcome));
+    }
+
+    if dry_run {
+        transaction.rollback()?;
+    } else {
+        transaction.commit()?;
+    }
+
+    Ok(report)
+}
+
+fn working_directory(cmd: &Command) -> Option<&str> {
+    Some(cmd.working_directory.as_str()).filter(|wd| !wd.is_empty())
+}
+
+fn import_command(
+    cmd: &Command,
+    db: &Db,
+    #[cfg(feature = "embeddings")] embed: &mut dyn FnMut(&str) -> Result<Vec<f32>>,
+) -> Result<()> {
+    let working_directory = working_directory(cmd);
+
+    #[cfg(feature = "embeddings")]
+    {
+        let embedding = embed(&embedding_text(&cmd.command, cmd.get_description()))?;
+        db.insert_command(
+            cmd.get_id(),
+            &cmd.command,
+            cmd.get_description(),
+            working_directory,
+            &embedding,
+        )?;
+    }
+    #[cfg(not(feature = "embeddings"))]
+    db.insert_command(
+        cmd.get_id(),
+        &cmd.command,
+        cmd.get_description(),
+        working_directory,
+    )?;
+
+    db.add_tags(cmd.get_id(), &cmd.get_tags())?;
+    db.set_group(cmd.get_id(), cmd.get_group())?;
+    db.set_use_count(cmd.get_id(), cmd.get_use_count())?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,6 +281,8 @@ mod tests {
         assert_eq!(store.commands.len(), 1);
         assert_eq!(store.commands[0].command, "ls -la");
         assert_eq!(store.commands[0].get_id(), "test-id-1");
+        assert_eq!(store.commands[0].get_group(), Some("file"));
+        assert_eq!(store.commands[0].get_tags(), vec!["list"]);
     }
 
     #[test]
@@ -136,4 +304,74 @@ mod tests {
         let all_commands = store.list_all();
         assert_eq!(all_commands.len(), 2);
     }
+
+    fn legacy_store() -> CommandStore {
+        serde_json::from_str(
+            r#"{
+            "commands": [
+                {
+                    "id": "id-1",
+                    "command": "ls -la",
+                    "working_directory": "/home/test",
+                    "group": "file",
+                    "tags": ["list", "fs"],
+                    "description": "List files",
+                    "use_count": 5
+                },
+                {"id": "id-2", "command": "ls -la", "working_directory": "/home/test"},
+                {"id": "id-3", "command": "   ", "working_directory": "/tmp"},
+                {"id": "id-4", "command": "make", "working_directory": "/src", "tags": ["bad tag"]}
+            ]
+        }"#,
+        )
+        .unwrap()
+    }
+
+    fn run_migration(store: &CommandStore, db: &Db, dry_run: bool) -> MigrationReport {
+        #[cfg(feature = "embeddings")]
+        let report = migrate(store, db, dry_run, &mut |_| Ok(vec![0.1f32; 384]));
+        #[cfg(not(feature = "embeddings"))]
+        let report = migrate(store, db, dry_run);
+        report.unwrap()
+    }
+
+    #[test]
+    fn test_migrate_preserves_fields_and_reports() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        let report = run_migration(&legacy_store(), &db, false);
+
+        assert_eq!(report.entries[0].2, ImportOutcome::Imported);
+        assert!(matches!(report.entries[1].2, ImportOutcome::Skipped(_)));
+        assert!(matches!(report.entries[2].2, ImportOutcome::Skipped(_)));
+        assert!(matches!(report.entries[3].2, ImportOutcome::Failed(_)));
+        assert!(report.has_failures());
+
+        let command = db.get_command("id-1").unwrap().unwrap();
+        assert_eq!(command.cmd, "ls -la");
+        assert_eq!(command.description.as_deref(), Some("List files"));
+        assert_eq!(command.working_directory.as_deref(), Some("/home/test"));
+        assert_eq!(command.group.as_deref(), Some("file"));
+        assert_eq!(command.tags, vec!["fs", "list"]);
+        assert_eq!(command.use_count, 5);
+
+        // The failed command is rolled back entirely
+        assert!(db.get_command("id-4").unwrap().is_none());
+
+        // Re-running skips what was already imported
+        let report = run_migration(&legacy_store(), &db, false);
+        assert_eq!(report.count(|o| *o == ImportOutcome::Imported), 0);
+    }
+
+    #[test]
+    fn test_migrate_dry_run_changes_nothing() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Db::new(temp_file.path()).unwrap();
+
+        let report = run_migration(&legacy_store(), &db, true);
+
+        assert_eq!(report.entries[0].2, ImportOutcome::Imported);
+        assert!(db.get_command("id-1").unwrap().is_none());
+    }
 }
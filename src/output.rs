@@ -0,0 +1,277 @@
+//! Machine-readable output of saved commands.
+//!
+//! Every format exposes the same fields, in the order of [`Record`]. Field names and
+//! their order are part of the interface scripts rely on: only ever append new ones.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::db::{SavedCommand, SearchResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A JSON array of objects (a single object for one command)
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    /// Tab-separated values with a header row
+    Tsv,
+    /// Only the command text, one per line
+    Plain,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 4] = ["json", "jsonl", "tsv", "plain"];
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "tsv" => Ok(Format::Tsv),
+            "plain" => Ok(Format::Plain),
+            _ => bail!(
+                "Unknown output format '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// A saved command as written by every format.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    pub id: &'a str,
+    pub cmd: &'a str,
+    pub working_directory: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub created_at: &'a str,
+    pub updated_at: Option<&'a str>,
+    pub group: Option<&'a str>,
+    pub tags: &'a [String],
+    pub use_count: u32,
+    pub last_used_at: Option<&'a str>,
+    /// Relevance in search results, higher is better; `null` outside of searches
+    pub score: Option<f64>,
+    /// Vector distance to the query in semantic and hybrid searches
+    pub distance: Option<f64>,
+}
+
+const TSV_HEADER: [&str; 12] = [
+    "id",
+    "cmd",
+    "working_directory",
+    "description",
+    "created_at",
+    "updated_at",
+    "group",
+    "tags",
+    "use_count",
+    "last_used_at",
+    "score",
+    "distance",
+];
+
+impl<'a> Record<'a> {
+    pub fn from_command(command: &'a SavedCommand) -> Self {
+        Self {
+            id: &command.id,
+            cmd: &command.cmd,
+            working_directory: command.working_directory.as_deref(),
+            description: command.description.as_deref(),
+            created_at: &command.created_at,
+            updated_at: command.updated_at.as_deref(),
+            group: command.group.as_deref(),
+            tags: &command.tags,
+            use_count: command.use_count,
+            last_used_at: command.last_used_at.as_deref(),
+            score: None,
+            distance: None,
+        }
+    }
+
+    pub fn from_result(result: &'a SearchResult) -> Self {
+        Self {
+            score: Some(result.score),
+            distance: result.distance,
+            ..Self::from_command(&result.command)
+        }
+    }
+
+    fn tsv_fields(&self) -> [String; 12] {
+        let text = |value: Option<&str>| value.map(escape_tsv).unwrap_or_default();
+        let number = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+        [
+            escape_tsv(self.id),
+            escape_tsv(self.cmd),
+            text(self.working_directory),
+            text(self.description),
+            escape_tsv(self.created_at),
+            text(self.updated_at),
+            text(self.group),
+            escape_tsv(&self.tags.join(",")),
+            self.use_count.to_string(),
+            text(self.last_used_at),
+            number(self.score),
+            number(self.distance),
+        ]
+    }
+}
+
+/// Writes a list of commands.
+pub fn write_records(output: &mut impl Write, format: Format, records: &[Record]) -> Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *output, records)?;
+            writeln!(output)?;
+        }
+        Format::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut *output, record)?;
+                writeln!(output)?;
+            }
+        }
+        Format::Tsv => {
+            writeln!(output, "{}", TSV_HEADER.join("\t"))?;
+            for record in records {
+                writeln!(output, "{}", record.tsv_fields().join("\t"))?;
+            }
+        }
+        Format::Plain => {
+            for record in records {
+                writeln!(output, "{}", record.cmd)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a single command. Same as `write_records`, except that JSON is a bare object.
+pub fn write_record(output: &mut impl Write, format: Format, record: &Record) -> Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *output, record)?;
+            writeln!(output)?;
+            Ok(())
+        }
+        _ => write_records(output, format, std::slice::from_ref(record)),
+    }
+}
+
+/// Escapes backslashes, tabs and line breaks so that every record stays on one line.
+fn escape_tsv(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> SavedCommand {
+        SavedCommand {
+            id: "id1".to_string(),
+            cmd: "printf 'a\\tb'\necho done".to_string(),
+            description: Some("Tabs".to_string()),
+            working_directory: None,
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            updated_at: None,
+            group: None,
+            tags: vec!["x".to_string(), "y".to_string()],
+            use_count: 2,
+            last_used_at: None,
+        }
+    }
+
+    fn render(format: Format, records: &[Record]) -> String {
+        let mut output = Vec::new();
+        write_records(&mut output, format, records).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let command = command();
+        let result = SearchResult {
+            command: command.clone(),
+            score: 1.5,
+            distance: None,
+            positions: vec![0],
+        };
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(Format::Json, &[Record::from_result(&result)])).unwrap();
+        let keys: Vec<&str> = json[0]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut expected = TSV_HEADER.to_vec();
+        expected.sort();
+        assert_eq!(keys, expected);
+        assert_eq!(json[0]["cmd"], command.cmd);
+        assert_eq!(json[0]["tags"], serde_json::json!(["x", "y"]));
+        assert_eq!(json[0]["score"], 1.5);
+        assert!(json[0]["working_directory"].is_null());
+
+        assert_eq!(render(Format::Json, &[]), "[]\n");
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let command = command();
+        let records = [
+            Record::from_command(&command),
+            Record::from_command(&command),
+        ];
+        let output = render(Format::Jsonl, &records);
+
+        assert_eq!(output.lines().count(), 2);
+        for line in output.lines() {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(json["id"], "id1");
+            assert!(json["score"].is_null());
+        }
+    }
+
+    #[test]
+    fn test_tsv() {
+        let command = command();
+        let output = render(Format::Tsv, &[Record::from_command(&command)]);
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split('\t').collect::<Vec<_>>(), TSV_HEADER);
+        let fields: Vec<&str> = lines[1].split('\t').collect();
+        assert_eq!(fields.len(), TSV_HEADER.len());
+        assert_eq!(fields[1], "printf 'a\\\\tb'\\necho done");
+        assert_eq!(fields[2], "");
+        assert_eq!(fields[7], "x,y");
+        assert_eq!(fields[8], "2");
+    }
+
+    #[test]
+    fn test_plain_and_single_record() {
+        let command = command();
+        let record = Record::from_command(&command);
+        assert_eq!(
+            render(Format::Plain, std::slice::from_ref(&record)),
+            format!("{}\n", command.cmd)
+        );
+
+        let mut output = Vec::new();
+        write_record(&mut output, Format::Json, &record).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["id"], "id1");
+    }
+}
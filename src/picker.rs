@@ -0,0 +1,1762 @@
+//! Interactive list picker.
+//!
+//! The picker is drawn on stderr so that stdout stays free for the output parsed by
+//! the shell integration. It takes the alternate screen, so that the scrollback is left
+//! alone, or a few rows below the shell prompt in inline mode (see [`Height`]). It
+//! either offers a fixed list of items ([`pick`]) or a query line whose results are
+//! searched again as the user types ([`search`]). Search results can also be deleted
+//! and edited from the picker.
+
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use crossterm::{
+    cursor::{Hide, MoveDown, MoveRight, MoveTo, MoveUp, RestorePosition, SavePosition, Show},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
+    execute, queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::io::Write;
+use std::ops::Range;
+use std::panic;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthChar;
+
+use crate::keymap::{Keymap, Lookup, PickerAction};
+
+/// Shown before the query of a live search.
+const PROMPT: &str = "Search: ";
+
+/// Rows an inline picker takes at least: the query, an item and the help.
+const MIN_INLINE_HEIGHT: usize = 3;
+
+/// Two clicks on the same item within this time pick it.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Terminals at least this wide show the preview next to the list rather than below it.
+const SIDE_PREVIEW_MIN_WIDTH: usize = 100;
+
+/// One line of the picker.
+#[derive(Debug, Clone, Default)]
+pub struct PickerItem {
+    pub text: String,
+    /// Character indices of `text` to highlight, ascending
+    pub positions: Vec<usize>,
+    /// Dimmed text shown after `text`
+    pub detail: Option<String>,
+    /// Lines shown in the preview pane while the item is selected
+    pub preview: Vec<String>,
+    /// Current tags and description, the starting point when editing them
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+}
+
+impl PickerItem {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Where the preview of the selected item is shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preview {
+    /// Not shown until toggled, then placed as with `Auto`
+    #[default]
+    Hidden,
+    /// Next to the list in wide terminals, below it otherwise
+    Auto,
+    Right,
+    Bottom,
+}
+
+impl Preview {
+    pub const NAMES: [&'static str; 4] = ["hidden", "auto", "right", "bottom"];
+}
+
+impl FromStr for Preview {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hidden" => Ok(Preview::Hidden),
+            "auto" => Ok(Preview::Auto),
+            "right" => Ok(Preview::Right),
+            "bottom" => Ok(Preview::Bottom),
+            _ => bail!(
+                "Unknown preview position '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// How much of the terminal the picker takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Height {
+    /// The whole screen, on the alternate screen
+    #[default]
+    Full,
+    /// This many rows below the cursor, leaving the rest of the screen visible
+    Lines(usize),
+    /// This percentage of the screen's rows below the cursor
+    Percent(usize),
+}
+
+impl Height {
+    /// Rows taken in a terminal of `rows` rows. Inline, that is at least one row, even if
+    /// the terminal reports none.
+    fn rows(self, rows: usize) -> usize {
+        let wanted = match self {
+            Height::Full => return rows,
+            Height::Lines(lines) => lines,
+            Height::Percent(percent) => rows * percent / 100,
+        };
+        wanted.max(MIN_INLINE_HEIGHT).min(rows).max(1)
+    }
+}
+
+impl FromStr for Height {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let height = match s.strip_suffix('%') {
+            _ if s == "full" => Some(Height::Full),
+            Some(percent) => percent
+                .parse()
+                .ok()
+                .filter(|percent| (1..=100).contains(percent))
+                .map(Height::Percent),
+            None => s.parse().ok().filter(|&lines| lines > 0).map(Height::Lines),
+        };
+        match height {
+            Some(height) => Ok(height),
+            None => bail!(
+                "Invalid height '{}', expected 'full', a number of rows or a percentage \
+                 of the screen (40%)",
+                s
+            ),
+        }
+    }
+}
+
+/// A change to a search result, made from the picker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    Delete,
+    Command(String),
+    Tags(Vec<String>),
+    Description(Option<String>),
+}
+
+/// Where the items of a live search come from, and where changes to them go.
+pub trait Catalog {
+    /// The items matching `query`.
+    fn search(&mut self, query: &str) -> Result<Vec<PickerItem>>;
+
+    /// Changes the item at `index` among those returned by the last search.
+    fn edit(&mut self, index: usize, edit: Edit) -> Result<()>;
+}
+
+/// The result picked from a live search, by index, and what to do with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Execute(usize),
+    /// Only print the command
+    Print(usize),
+}
+
+/// What a key press did to the picker.
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    Continue,
+    Accept,
+    Cancel,
+}
+
+/// Cursor, scroll position and marks of a picker, independent of the terminal.
+#[derive(Debug)]
+struct PickerState {
+    selected: usize,
+    /// Index of the first visible item
+    offset: usize,
+    /// Number of items that fit on the screen
+    height: usize,
+    /// Marked items; only used when several items may be picked
+    marked: Vec<bool>,
+    multi_select: bool,
+}
+
+impl PickerState {
+    fn new(len: usize, multi_select: bool) -> Self {
+        Self {
+            selected: 0,
+            offset: 0,
+            height: len.max(1),
+            marked: vec![false; len],
+            multi_select,
+        }
+    }
+
+    fn last(&self) -> usize {
+        self.marked.len().saturating_sub(1)
+    }
+
+    fn handle(&mut self, action: PickerAction) -> Step {
+        match action {
+            PickerAction::Up => self.selected = self.selected.saturating_sub(1),
+            PickerAction::Down => self.selected = (self.selected + 1).min(self.last()),
+            PickerAction::PageUp => self.selected = self.selected.saturating_sub(self.height),
+            PickerAction::PageDown => {
+                self.selected = (self.selected + self.height).min(self.last())
+            }
+            PickerAction::First => self.selected = 0,
+            PickerAction::Last => self.selected = self.last(),
+            PickerAction::Jump(n) if (1..=self.marked.len()).contains(&n) => self.selected = n - 1,
+            PickerAction::Mark if self.multi_select => {
+                self.marked[self.selected] = !self.marked[self.selected];
+                self.selected = (self.selected + 1).min(self.last());
+            }
+            // A live search may have no results to pick from
+            PickerAction::Accept if !self.marked.is_empty() => return Step::Accept,
+            PickerAction::Cancel => return Step::Cancel,
+            _ => {}
+        }
+        self.scroll_to_selection();
+        Step::Continue
+    }
+
+    /// Sets how many items fit on the screen, e.g. after the terminal was resized.
+    fn set_height(&mut self, height: usize) {
+        self.height = height.max(1);
+        self.scroll_to_selection();
+    }
+
+    /// Scrolls as little as possible to show the selected item.
+    fn scroll_to_selection(&mut self) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + self.height {
+            self.offset = self.selected + 1 - self.height;
+        }
+        // Do not leave rows empty at the bottom while items are hidden at the top
+        self.offset = self
+            .offset
+            .min(self.marked.len().saturating_sub(self.height));
+    }
+
+    /// Indices of the items on the screen.
+    fn visible(&self) -> Range<usize> {
+        self.offset..(self.offset + self.height).min(self.marked.len())
+    }
+
+    /// The marked items, or the one under the cursor if none is marked.
+    fn picked(&self) -> Vec<usize> {
+        let marked: Vec<usize> = (0..self.marked.len()).filter(|&i| self.marked[i]).collect();
+        if marked.is_empty() {
+            vec![self.selected]
+        } else {
+            marked
+        }
+    }
+}
+
+/// A line typed by the user: the query of a live search or the answer to a prompt.
+#[derive(Debug, Default)]
+struct QueryInput {
+    text: String,
+}
+
+impl QueryInput {
+    /// Applies an editing key, returning whether the key was one. Characters typed
+    /// without Ctrl or Alt always go to the query, even if a picker action is bound to them.
+    fn handle_key(&mut self, key: &KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('u') if ctrl => self.text.clear(),
+            KeyCode::Char('w') if ctrl => {
+                let start = self
+                    .text
+                    .trim_end()
+                    .rfind(char::is_whitespace)
+                    .map_or(0, |i| i + 1);
+                self.text.truncate(start);
+            }
+            KeyCode::Char(c) if !ctrl && !alt => self.text.push(c),
+            KeyCode::Backspace => {
+                self.text.pop();
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The query of a live search and the search it drives.
+struct LiveSearch<'a> {
+    input: QueryInput,
+    /// The query the current items were found for; `None` before the first search
+    searched: Option<String>,
+    /// When the query was last edited; `None` to search right away
+    edited_at: Option<Instant>,
+    debounce: Duration,
+    /// Shown instead of the items when the last search failed
+    error: Option<String>,
+    catalog: &'a mut dyn Catalog,
+}
+
+impl LiveSearch<'_> {
+    fn is_stale(&self) -> bool {
+        self.searched.as_deref() != Some(self.input.text.as_str())
+    }
+
+    /// How long to wait for more keys before searching.
+    fn remaining_debounce(&self) -> Duration {
+        self.edited_at.map_or(Duration::ZERO, |edited_at| {
+            self.debounce.saturating_sub(edited_at.elapsed())
+        })
+    }
+}
+
+/// A question asked on the help line before changing the selected item.
+struct Prompt {
+    /// `Delete`, `Edit`, `Tag` or `Describe`
+    action: PickerAction,
+    input: QueryInput,
+}
+
+impl Prompt {
+    fn label(&self, item: &PickerItem) -> String {
+        match self.action {
+            PickerAction::Delete => format!("Delete '{}'? (y/N) ", item.text),
+            PickerAction::Edit => "Command: ".to_string(),
+            PickerAction::Tag => "Tags: ".to_string(),
+            _ => "Description: ".to_string(),
+        }
+    }
+
+    /// The change the answer asks for.
+    fn edit(self) -> Edit {
+        let text = self.input.text;
+        match self.action {
+            PickerAction::Delete => Edit::Delete,
+            PickerAction::Edit => Edit::Command(text),
+            PickerAction::Tag => Edit::Tags(
+                text.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            _ => Edit::Description(Some(text.trim().to_string()).filter(|d| !d.is_empty())),
+        }
+    }
+}
+
+/// A picker on the terminal.
+struct Picker<'a> {
+    items: Vec<PickerItem>,
+    state: PickerState,
+    help: &'a str,
+    keymap: &'a Keymap,
+    search: Option<LiveSearch<'a>>,
+    height: Height,
+    preview: Preview,
+    show_preview: bool,
+    prompt: Option<Prompt>,
+    /// Shown instead of the help until the next key press
+    status: Option<String>,
+    /// Whether the pick was made to print rather than execute the command
+    print: bool,
+    /// Whether keys go to the query; only ever false with a modal keymap
+    typing: bool,
+    /// Keys pressed so far of a sequence such as `g g`
+    pending: Vec<KeyEvent>,
+    /// The item last clicked, and when
+    last_click: Option<(usize, Instant)>,
+}
+
+/// Lets the user pick one item, or several if `multi_select` is set.
+///
+/// Returns the indices of the picked items in ascending order, or `None` if the user
+/// cancelled. `help` is shown below the list.
+pub fn pick(
+    items: &[PickerItem],
+    multi_select: bool,
+    height: Height,
+    help: &str,
+    keymap: &Keymap,
+) -> Result<Option<Vec<usize>>> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    Picker {
+        items: items.to_vec(),
+        state: PickerState::new(items.len(), multi_select),
+        help,
+        keymap,
+        search: None,
+        height,
+        preview: Preview::Hidden,
+        show_preview: false,
+        prompt: None,
+        status: None,
+        print: false,
+        typing: false,
+        pending: Vec::new(),
+        last_click: None,
+    }
+    .run()
+}
+
+/// Lets the user edit a query, starting from `query`, and pick one of the items that
+/// `catalog` returns for it.
+///
+/// The catalog is searched again whenever the query changes, once no key has been
+/// pressed for `debounce`, and after every change made to a result. The preview of the
+/// selected item starts at `preview` and can be toggled. Returns the picked item among
+/// those returned by the last search, or `None` if the user cancelled.
+pub fn search(
+    query: &str,
+    debounce: Duration,
+    height: Height,
+    preview: Preview,
+    help: &str,
+    keymap: &Keymap,
+    catalog: &mut dyn Catalog,
+) -> Result<Option<Choice>> {
+    let mut picker = Picker {
+        items: Vec::new(),
+        state: PickerState::new(0, false),
+        help,
+        keymap,
+        search: Some(LiveSearch {
+            input: QueryInput {
+                text: query.to_string(),
+            },
+            searched: None,
+            edited_at: None,
+            debounce,
+            error: None,
+            catalog,
+        }),
+        height,
+        preview,
+        show_preview: preview != Preview::Hidden,
+        prompt: None,
+        status: None,
+        print: false,
+        typing: !keymap.modal,
+        pending: Vec::new(),
+        last_click: None,
+    };
+    let picked = picker.run()?.and_then(|picked| picked.first().copied());
+    Ok(picked.map(|index| match picker.print {
+        true => Choice::Print(index),
+        false => Choice::Execute(index),
+    }))
+}
+
+/// Where the parts of the picker go on the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    width: usize,
+    /// Width of the list, narrower than the terminal next to a preview
+    list_width: usize,
+    /// Row of the first item, below the query line if there is one
+    top: usize,
+    /// Row of the help text, below the items
+    help: usize,
+    preview: Option<Pane>,
+    /// Rows are counted from the saved cursor position rather than the top of the screen
+    inline: bool,
+}
+
+impl Layout {
+    /// Moves the cursor to a cell of the picker.
+    fn goto(&self, output: &mut impl Write, column: usize, row: usize) -> Result<()> {
+        if !self.inline {
+            queue!(output, MoveTo(column as u16, row as u16))?;
+            return Ok(());
+        }
+        queue!(output, RestorePosition)?;
+        // Moving by zero would move by one
+        if row > 0 {
+            queue!(output, MoveDown(row as u16))?;
+        }
+        if column > 0 {
+            queue!(output, MoveRight(column as u16))?;
+        }
+        Ok(())
+    }
+}
+
+/// The area of the preview pane, without its separator line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pane {
+    /// `Right` or `Bottom`
+    position: Preview,
+    row: usize,
+    column: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Whether the terminal is set up for the picker, and must be restored.
+static TERMINAL_IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the picker is on the alternate screen, which must be left.
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Whether mouse reporting is on, and must be turned off.
+static MOUSE_REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// Reports clicks and the wheel, in SGR encoding. Unlike crossterm's
+/// `EnableMouseCapture`, this leaves out mouse moves, each of which would redraw.
+const ENABLE_MOUSE: &str = "\x1b[?1000h\x1b[?1006h";
+const DISABLE_MOUSE: &str = "\x1b[?1006l\x1b[?1000l";
+
+/// The terminal set up for the picker: raw mode, on the alternate screen or below the
+/// cursor.
+///
+/// The terminal is restored when the session is dropped, so also when the picker fails
+/// with an error, and by a panic hook before the panic message is printed.
+struct TerminalSession {
+    /// Rows kept below the cursor for an inline picker, which must not draw past them
+    reserved: Option<usize>,
+}
+
+impl TerminalSession {
+    fn start(height: Height, mouse: bool) -> Result<Self> {
+        static PANIC_HOOK: Once = Once::new();
+        PANIC_HOOK.call_once(|| {
+            let default_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore_terminal();
+                default_hook(info);
+            }));
+        });
+
+        terminal::enable_raw_mode()?;
+        TERMINAL_IN_USE.store(true, Ordering::SeqCst);
+        // Created before anything else can fail, so that dropping it undoes the rest
+        let mut session = TerminalSession { reserved: None };
+        let mut output = std::io::stderr();
+        // Inline, the picker cannot tell which rows of the screen it is on: crossterm asks
+        // the terminal for the cursor position on stdout, which the shell integration
+        // captures. The mouse is left to the terminal there, e.g. to select text.
+        if mouse && height == Height::Full {
+            MOUSE_REPORTING.store(true, Ordering::SeqCst);
+            queue!(output, Print(ENABLE_MOUSE))?;
+        }
+        if height == Height::Full {
+            ALTERNATE_SCREEN.store(true, Ordering::SeqCst);
+            execute!(output, EnterAlternateScreen, Hide)?;
+            return Ok(session);
+        }
+
+        // Make room below the cursor, scrolling the screen if needed, and remember where
+        // the picker starts
+        let rows = height.rows(terminal::size()?.1 as usize);
+        session.reserved = Some(rows);
+        queue!(
+            output,
+            Print("\r"),
+            Print("\n".repeat(rows.saturating_sub(1)))
+        )?;
+        if rows > 1 {
+            queue!(output, MoveUp(rows as u16 - 1))?;
+        }
+        execute!(output, SavePosition, Hide)?;
+        Ok(session)
+    }
+}
+
+impl Drop for TerminalSession {
+    fn drop(&mut self) {
+        if self.reserved.is_some() {
+            // Leave the prompt where the picker was
+            let _ = execute!(
+                std::io::stderr(),
+                RestorePosition,
+                Clear(ClearType::FromCursorDown)
+            );
+        }
+        restore_terminal();
+    }
+}
+
+/// Leaves the alternate screen and raw mode, if the picker is using the terminal.
+fn restore_terminal() {
+    if TERMINAL_IN_USE.swap(false, Ordering::SeqCst) {
+        // Nothing can be done about errors while restoring
+        if MOUSE_REPORTING.swap(false, Ordering::SeqCst) {
+            let _ = execute!(std::io::stderr(), Print(DISABLE_MOUSE));
+        }
+        if ALTERNATE_SCREEN.swap(false, Ordering::SeqCst) {
+            let _ = execute!(std::io::stderr(), LeaveAlternateScreen);
+        }
+        let _ = execute!(std::io::stderr(), Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Picker<'_> {
+    fn run(&mut self) -> Result<Option<Vec<usize>>> {
+        let session = TerminalSession::start(self.height, self.keymap.mouse)?;
+        let mut output = std::io::stderr();
+
+        loop {
+            self.refresh();
+            // Measured on every frame, so that a resize is picked up by the next redraw
+            let (columns, rows) = terminal::size()?;
+            let rows = match session.reserved {
+                // Growing the terminal does not add rows below the cursor
+                Some(reserved) => self.height.rows(rows as usize).min(reserved),
+                None => rows as usize,
+            };
+            let layout = self.layout((columns, rows as u16));
+            self.draw(&mut output, layout)?;
+
+            // While a search is pending, only wait for keys until it is due
+            if let Some(search) = self.search.as_ref().filter(|search| search.is_stale()) {
+                if !event::poll(search.remaining_debounce())? {
+                    continue;
+                }
+            }
+
+            let key_event = match event::read()? {
+                Event::Key(key_event) => key_event,
+                Event::Mouse(mouse_event) if self.prompt.is_none() => {
+                    match self.mouse(mouse_event, layout) {
+                        Step::Accept => return Ok(Some(self.state.picked())),
+                        _ => continue,
+                    }
+                }
+                // Redraw at the new size
+                Event::Resize(..) => continue,
+                _ => continue,
+            };
+            self.status = None;
+            if let Some(prompt) = self.prompt.take() {
+                if self.answer(prompt, &key_event) == Step::Cancel {
+                    return Ok(None);
+                }
+                continue;
+            }
+            if let Some(search) = self.search.as_mut().filter(|_| self.typing) {
+                // In a modal keymap, Esc leaves the query rather than the picker
+                if self.keymap.modal && key_event.code == KeyCode::Esc {
+                    self.typing = false;
+                    continue;
+                }
+                if search.input.handle_key(&key_event) {
+                    search.edited_at = Some(Instant::now());
+                    continue;
+                }
+            }
+            let Some(action) = self.action(&key_event) else {
+                continue;
+            };
+            let editable = self.search.is_some() && !self.items.is_empty();
+            match action {
+                PickerAction::Search if self.search.is_some() => self.typing = true,
+                PickerAction::Preview => self.show_preview = !self.show_preview,
+                PickerAction::Copy => self.copy(&mut output)?,
+                PickerAction::Delete
+                | PickerAction::Edit
+                | PickerAction::Tag
+                | PickerAction::Describe
+                    if editable =>
+                {
+                    self.ask(action)
+                }
+                PickerAction::Print if editable => {
+                    self.print = true;
+                    return Ok(Some(self.state.picked()));
+                }
+                _ => {}
+            }
+            match self.state.handle(action) {
+                Step::Continue => {}
+                Step::Accept => return Ok(Some(self.state.picked())),
+                Step::Cancel => return Ok(None),
+            }
+        }
+    }
+
+    /// The action of a key press, once it completes a sequence of keys. Ctrl-C always
+    /// cancels, whatever the keymap says, since raw mode keeps it from interrupting the
+    /// program.
+    fn action(&mut self, key: &KeyEvent) -> Option<PickerAction> {
+        if is_interrupt(key) {
+            self.pending.clear();
+            return Some(PickerAction::Cancel);
+        }
+        self.pending.push(*key);
+        match self.keymap.lookup(&self.pending) {
+            Lookup::Action(action) => {
+                self.pending.clear();
+                Some(action)
+            }
+            Lookup::Pending => None,
+            // Start over, from this key if it did not start the sequence
+            Lookup::None if self.pending.len() > 1 => {
+                self.pending.clear();
+                self.action(key)
+            }
+            Lookup::None => {
+                self.pending.clear();
+                None
+            }
+        }
+    }
+
+    /// Selects the clicked item, picks it on a double click, and scrolls with the wheel.
+    fn mouse(&mut self, event: MouseEvent, layout: Layout) -> Step {
+        match event.kind {
+            MouseEventKind::ScrollUp => self.state.handle(PickerAction::Up),
+            MouseEventKind::ScrollDown => self.state.handle(PickerAction::Down),
+            MouseEventKind::Down(MouseButton::Left) => {
+                let (column, row) = (event.column as usize, event.row as usize);
+                let visible = self.state.visible();
+                if column >= layout.list_width
+                    || !(layout.top..layout.top + visible.len()).contains(&row)
+                {
+                    return Step::Continue;
+                }
+
+                let index = visible.start + row - layout.top;
+                let double_click = self
+                    .last_click
+                    .is_some_and(|(clicked, at)| clicked == index && at.elapsed() < DOUBLE_CLICK);
+                self.last_click = Some((index, Instant::now()));
+                self.state.selected = index;
+                if double_click {
+                    self.state.handle(PickerAction::Accept)
+                } else {
+                    Step::Continue
+                }
+            }
+            _ => Step::Continue,
+        }
+    }
+
+    /// Runs the search again if the query changed and no key was pressed for a while.
+    fn refresh(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        if !search.is_stale() || !search.remaining_debounce().is_zero() {
+            return;
+        }
+        self.reload();
+        self.state = PickerState::new(self.items.len(), self.state.multi_select);
+    }
+
+    /// Searches for the current query right away.
+    fn reload(&mut self) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let query = search.input.text.clone();
+        match search.catalog.search(&query) {
+            Ok(items) => {
+                search.error = None;
+                self.items = items;
+            }
+            Err(e) => {
+                search.error = Some(format!("{:#}", e));
+                self.items.clear();
+            }
+        }
+        search.searched = Some(query);
+    }
+
+    /// Asks for the change `action` makes to the selected item.
+    fn ask(&mut self, action: PickerAction) {
+        let item = &self.items[self.state.selected];
+        let text = match action {
+            PickerAction::Edit => item.text.clone(),
+            PickerAction::Tag => item.tags.join(" "),
+            PickerAction::Describe => item.description.clone().unwrap_or_default(),
+            _ => String::new(),
+        };
+        self.prompt = Some(Prompt {
+            action,
+            input: QueryInput { text },
+        });
+    }
+
+    /// Handles a key pressed while a prompt is shown. The cancel keys only close the
+    /// prompt, while Ctrl-C closes the picker as well.
+    fn answer(&mut self, mut prompt: Prompt, key: &KeyEvent) -> Step {
+        if is_interrupt(key) {
+            return Step::Cancel;
+        }
+        if prompt.action == PickerAction::Delete {
+            // Anything but yes keeps the command
+            if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                self.apply(Edit::Delete);
+            }
+            return Step::Continue;
+        }
+
+        if prompt.input.handle_key(key) {
+            self.prompt = Some(prompt);
+            return Step::Continue;
+        }
+        match self.keymap.action(key) {
+            Some(PickerAction::Accept) => self.apply(prompt.edit()),
+            Some(PickerAction::Cancel) => {}
+            _ => self.prompt = Some(prompt),
+        }
+        Step::Continue
+    }
+
+    /// Changes the selected item, then searches again, keeping the selection in place.
+    fn apply(&mut self, edit: Edit) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let selected = self.state.selected;
+        let status = match edit {
+            Edit::Delete => "Command deleted",
+            _ => "Command updated",
+        };
+        match search.catalog.edit(selected, edit) {
+            Ok(()) => self.status = Some(status.to_string()),
+            Err(e) => self.status = Some(format!("Error: {:#}", e)),
+        }
+
+        self.reload();
+        self.state = PickerState::new(self.items.len(), self.state.multi_select);
+        self.state.selected = selected.min(self.state.last());
+    }
+
+    /// Copies the selected item to the clipboard, through the terminal (OSC 52).
+    fn copy(&mut self, output: &mut impl Write) -> Result<()> {
+        let Some(item) = self.items.get(self.state.selected) else {
+            return Ok(());
+        };
+        queue!(
+            output,
+            Print(format!("\x1b]52;c;{}\x07", BASE64.encode(&item.text)))
+        )?;
+        self.status = Some("Copied to the clipboard".to_string());
+        Ok(())
+    }
+
+    /// Fits the picker into a terminal of `(columns, rows)`, scrolling the list if needed.
+    fn layout(&mut self, (columns, rows): (u16, u16)) -> Layout {
+        let (width, rows) = (columns as usize, rows as usize);
+        let top = usize::from(self.search.is_some());
+        // One row is kept for the help text
+        let available = rows.saturating_sub(top + 1);
+
+        let position = match self.preview {
+            _ if !self.show_preview => None,
+            Preview::Hidden | Preview::Auto if width >= SIDE_PREVIEW_MIN_WIDTH => {
+                Some(Preview::Right)
+            }
+            Preview::Hidden | Preview::Auto => Some(Preview::Bottom),
+            position => Some(position),
+        };
+        // The list keeps at least half of the screen, and a column or row separates it
+        // from the preview
+        let (list_width, pane_width, pane_rows) = match position {
+            Some(Preview::Right) => (width / 2, width - width / 2 - 1, available),
+            Some(_) => (width, width, (available / 2).saturating_sub(1)),
+            None => (width, 0, 0),
+        };
+        let lines = self.preview_lines(pane_width).len().min(pane_rows);
+
+        let list_rows = match position {
+            Some(Preview::Bottom) if lines > 0 => available - lines - 1,
+            _ => available,
+        };
+        self.state.set_height(list_rows);
+        // An empty list still takes a row, for the message saying so
+        let rows_used = self.state.visible().len().max(1);
+
+        let preview = position
+            .filter(|_| lines > 0)
+            .map(|position| match position {
+                Preview::Right => Pane {
+                    position,
+                    row: top,
+                    column: list_width + 1,
+                    width: pane_width,
+                    height: lines,
+                },
+                _ => Pane {
+                    position,
+                    row: top + rows_used + 1,
+                    column: 0,
+                    width: pane_width,
+                    height: lines,
+                },
+            });
+        let help = match preview {
+            Some(pane) if pane.position == Preview::Right => top + rows_used.max(pane.height),
+            Some(pane) => pane.row + pane.height,
+            None => top + rows_used,
+        };
+        Layout {
+            width,
+            list_width: if preview.is_some() { list_width } else { width },
+            top,
+            help,
+            preview,
+            inline: self.height != Height::Full,
+        }
+    }
+
+    /// The preview of the selected item, wrapped to `width`.
+    fn preview_lines(&self, width: usize) -> Vec<Line> {
+        let Some(item) = self.items.get(self.state.selected) else {
+            return Vec::new();
+        };
+        item.preview
+            .iter()
+            .flat_map(|text| text.lines())
+            .flat_map(|text| {
+                let mut line = Line::default();
+                line.push(text, Style::Plain);
+                line.wrap(width)
+            })
+            .collect()
+    }
+
+    fn draw(&self, output: &mut impl Write, layout: Layout) -> Result<()> {
+        // Clear screen and reset cursor
+        layout.goto(output, 0, 0)?;
+        queue!(output, Clear(ClearType::FromCursorDown), Hide)?;
+
+        if let Some(search) = &self.search {
+            let message = match &search.error {
+                Some(error) => Some(format!("Search failed: {}", error)),
+                None if self.items.is_empty() && !search.is_stale() => {
+                    Some("No matching commands".to_string())
+                }
+                None => None,
+            };
+            if let Some(message) = message {
+                let mut line = Line::default();
+                line.push(&message, Style::Dim);
+                line.truncate(layout.width);
+                layout.goto(output, 0, layout.top)?;
+                line.print(output)?;
+            }
+        }
+
+        let number_width = self.items.len().to_string().len();
+        for (row, i) in self.state.visible().enumerate() {
+            let item = &self.items[i];
+            let mut line = Line::default();
+            line.push(
+                if i == self.state.selected { "> " } else { "  " },
+                Style::Plain,
+            );
+            match (self.state.multi_select, self.state.marked[i]) {
+                (false, _) => {}
+                (true, false) => line.push("[ ] ", Style::Plain),
+                (true, true) => line.push("[x] ", Style::Plain),
+            }
+            line.push(&format!("{:>number_width$}. ", i + 1), Style::Plain);
+            line.push_highlighted(&item.text, &item.positions);
+            if let Some(detail) = &item.detail {
+                line.push(&format!("  {}", detail), Style::Dim);
+            }
+            line.truncate(layout.list_width);
+
+            layout.goto(output, 0, layout.top + row)?;
+            line.print(output)?;
+        }
+
+        if let Some(pane) = layout.preview {
+            self.draw_preview(output, layout, pane)?;
+        }
+
+        if self.prompt.is_none() {
+            let mut help = Line::default();
+            help.push(self.status.as_deref().unwrap_or(self.help), Style::Plain);
+            help.truncate(layout.width);
+            layout.goto(output, 0, layout.help)?;
+            help.print(output)?;
+        }
+
+        let mut inputs = Vec::new();
+        if let Some(search) = &self.search {
+            let focused = self.typing && self.prompt.is_none();
+            inputs.push((PROMPT.to_string(), &search.input.text, 0, focused));
+        }
+        if let Some(prompt) = &self.prompt {
+            let label = prompt.label(&self.items[self.state.selected]);
+            inputs.push((label, &prompt.input.text, layout.help, true));
+        }
+        // Leave the cursor at the end of the input keys go to, if any
+        let mut cursor = None;
+        for (label, text, row, focused) in inputs {
+            let mut line = Line::default();
+            line.push(&label, Style::Plain);
+            line.push(text, Style::Plain);
+            // Keep the end of a long text in view, and a column for the cursor
+            line.truncate_start(layout.width.saturating_sub(1));
+            layout.goto(output, 0, row)?;
+            line.print(output)?;
+            if focused {
+                cursor = Some((line.width(), row));
+            }
+        }
+        if let Some((column, row)) = cursor {
+            layout.goto(output, column, row)?;
+            queue!(output, Show)?;
+        }
+
+        output.flush()?;
+        Ok(())
+    }
+
+    fn draw_preview(&self, output: &mut impl Write, layout: Layout, pane: Pane) -> Result<()> {
+        let mut separator = Line::default();
+        if pane.position == Preview::Right {
+            separator.push("│", Style::Dim);
+            for row in pane.row..pane.row + pane.height {
+                layout.goto(output, pane.column - 1, row)?;
+                separator.print(output)?;
+            }
+        } else {
+            separator.push(&"─".repeat(pane.width), Style::Dim);
+            layout.goto(output, 0, pane.row - 1)?;
+            separator.print(output)?;
+        }
+
+        for (row, line) in self
+            .preview_lines(pane.width)
+            .iter()
+            .take(pane.height)
+            .enumerate()
+        {
+            layout.goto(output, pane.column, pane.row + row)?;
+            line.print(output)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Plain,
+    /// Characters matched by the query
+    Highlight,
+    Dim,
+}
+
+/// A line of styled characters, which can be cut to the width of the terminal.
+#[derive(Debug, Default)]
+struct Line {
+    chars: Vec<(char, Style)>,
+}
+
+impl Line {
+    fn push(&mut self, text: &str, style: Style) {
+        self.chars
+            .extend(text.chars().map(|c| (printable(c), style)));
+    }
+
+    /// Pushes `text`, highlighting the characters at the given (ascending) char indices.
+    fn push_highlighted(&mut self, text: &str, positions: &[usize]) {
+        let mut positions = positions.iter().peekable();
+        for (i, c) in text.chars().enumerate() {
+            let style = if positions.next_if_eq(&&i).is_some() {
+                Style::Highlight
+            } else {
+                Style::Plain
+            };
+            self.chars.push((printable(c), style));
+        }
+    }
+
+    /// Width in terminal columns.
+    fn width(&self) -> usize {
+        self.chars.iter().map(|&(c, _)| char_width(c)).sum()
+    }
+
+    /// Cuts the end of the line so that it fits in `width` columns, marking the cut
+    /// with an ellipsis.
+    fn truncate(&mut self, width: usize) {
+        if self.width() <= width {
+            return;
+        }
+        let kept = Self::fitting(self.chars.iter(), width.saturating_sub(1));
+        self.chars.truncate(kept);
+        if width > 0 {
+            self.chars.push(('…', Style::Dim));
+        }
+    }
+
+    /// Like `truncate`, but cuts the start of the line.
+    fn truncate_start(&mut self, width: usize) {
+        if self.width() <= width {
+            return;
+        }
+        let kept = Self::fitting(self.chars.iter().rev(), width.saturating_sub(1));
+        self.chars.drain(..self.chars.len() - kept);
+        if width > 0 {
+            self.chars.insert(0, ('…', Style::Dim));
+        }
+    }
+
+    /// Splits the line into lines of at most `width` columns.
+    fn wrap(self, width: usize) -> Vec<Line> {
+        let mut lines = vec![Line::default()];
+        let mut used = 0;
+        for (c, style) in self.chars {
+            let c_width = char_width(c);
+            if used + c_width > width {
+                // A character wider than the line cannot be shown at all
+                if c_width > width {
+                    continue;
+                }
+                lines.push(Line::default());
+                used = 0;
+            }
+            used += c_width;
+            lines.last_mut().unwrap().chars.push((c, style));
+        }
+        lines
+    }
+
+    /// How many of `chars` fit in `width` columns.
+    fn fitting<'a>(chars: impl Iterator<Item = &'a (char, Style)>, width: usize) -> usize {
+        let mut used = 0;
+        chars
+            .take_while(|&&(c, _)| {
+                used += char_width(c);
+                used <= width
+            })
+            .count()
+    }
+
+    fn print(&self, output: &mut impl Write) -> Result<()> {
+        for run in self.chars.chunk_by(|a, b| a.1 == b.1) {
+            let text: String = run.iter().map(|&(c, _)| c).collect();
+            match run[0].1 {
+                Style::Plain => queue!(output, Print(text))?,
+                Style::Highlight => queue!(
+                    output,
+                    SetForegroundColor(Color::Yellow),
+                    SetAttribute(Attribute::Bold),
+                    Print(text),
+                    SetAttribute(Attribute::Reset),
+                    ResetColor,
+                )?,
+                Style::Dim => queue!(
+                    output,
+                    SetAttribute(Attribute::Dim),
+                    Print(text),
+                    SetAttribute(Attribute::Reset),
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_interrupt(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Replaces characters that would move the cursor, so that every item stays on its row.
+/// Keeps one character per character, so that match positions stay valid.
+fn printable(c: char) -> char {
+    match c {
+        '\n' => '↵',
+        c if c.is_control() => ' ',
+        c => c,
+    }
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::parse_keys;
+
+    #[test]
+    fn test_single_select() {
+        let mut state = PickerState::new(3, false);
+
+        assert_eq!(state.handle(PickerAction::Down), Step::Continue);
+        assert_eq!(state.handle(PickerAction::Down), Step::Continue);
+        assert_eq!(state.handle(PickerAction::Down), Step::Continue);
+        assert_eq!(state.selected, 2);
+
+        // Nothing is marked without multi-select
+        state.handle(PickerAction::Mark);
+        assert_eq!(state.handle(PickerAction::Accept), Step::Accept);
+        assert_eq!(state.picked(), vec![2]);
+    }
+
+    #[test]
+    fn test_multi_select() {
+        let mut state = PickerState::new(4, true);
+
+        // Marking moves the cursor down
+        state.handle(PickerAction::Mark);
+        state.handle(PickerAction::Down);
+        state.handle(PickerAction::Mark);
+        assert_eq!(state.selected, 3);
+        assert_eq!(state.picked(), vec![0, 2]);
+
+        // Unmark
+        state.handle(PickerAction::Up);
+        state.handle(PickerAction::Mark);
+        assert_eq!(state.picked(), vec![0]);
+    }
+
+    #[test]
+    fn test_empty_list_cannot_be_accepted() {
+        let mut state = PickerState::new(0, false);
+        assert_eq!(state.handle(PickerAction::Down), Step::Continue);
+        assert_eq!(state.handle(PickerAction::Accept), Step::Continue);
+        assert_eq!(state.handle(PickerAction::Cancel), Step::Cancel);
+    }
+
+    #[test]
+    fn test_query_input() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let mut input = QueryInput::default();
+
+        for c in "git  log".chars() {
+            assert!(input.handle_key(&key(KeyCode::Char(c))));
+        }
+        assert!(input.handle_key(&KeyEvent::new(KeyCode::Char('S'), KeyModifiers::SHIFT)));
+        assert_eq!(input.text, "git  logS");
+
+        input.handle_key(&key(KeyCode::Backspace));
+        assert_eq!(input.text, "git  log");
+        input.handle_key(&ctrl('w'));
+        assert_eq!(input.text, "git  ");
+        input.handle_key(&ctrl('w'));
+        assert_eq!(input.text, "");
+        input.handle_key(&key(KeyCode::Backspace));
+
+        input.text = "docker ps".to_string();
+        input.handle_key(&ctrl('u'));
+        assert_eq!(input.text, "");
+
+        // Navigation keys are left to the keymap
+        assert!(!input.handle_key(&key(KeyCode::Up)));
+        assert!(!input.handle_key(&key(KeyCode::Enter)));
+        assert!(!input.handle_key(&ctrl('n')));
+    }
+
+    #[test]
+    fn test_picks_cursor_when_nothing_marked() {
+        let mut state = PickerState::new(2, true);
+        state.handle(PickerAction::Down);
+        assert_eq!(state.picked(), vec![1]);
+        assert_eq!(state.handle(PickerAction::Cancel), Step::Cancel);
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let mut state = PickerState::new(10, false);
+        state.set_height(3);
+        assert_eq!(state.visible(), 0..3);
+
+        // The list scrolls once the cursor leaves the screen
+        state.handle(PickerAction::Down);
+        state.handle(PickerAction::Down);
+        assert_eq!(state.visible(), 0..3);
+        state.handle(PickerAction::Down);
+        assert_eq!(state.visible(), 1..4);
+
+        state.handle(PickerAction::PageDown);
+        assert_eq!(state.selected, 6);
+        assert_eq!(state.visible(), 4..7);
+        state.handle(PickerAction::PageUp);
+        assert_eq!(state.selected, 3);
+        assert_eq!(state.visible(), 3..6);
+
+        state.handle(PickerAction::Last);
+        assert_eq!(state.selected, 9);
+        assert_eq!(state.visible(), 7..10);
+        state.handle(PickerAction::PageDown);
+        assert_eq!(state.selected, 9);
+        state.handle(PickerAction::First);
+        assert_eq!(state.visible(), 0..3);
+
+        // Growing the terminal shows more items, without empty rows at the bottom
+        state.handle(PickerAction::Last);
+        state.set_height(5);
+        assert_eq!(state.visible(), 5..10);
+        state.set_height(20);
+        assert_eq!(state.visible(), 0..10);
+        state.set_height(0);
+        assert_eq!(state.visible(), 9..10);
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut state = PickerState::new(10, false);
+        state.set_height(3);
+        state.handle(PickerAction::Jump(5));
+        assert_eq!(state.selected, 4);
+        assert_eq!(state.visible(), 2..5);
+        // Past the end of the list, nothing happens
+        state.handle(PickerAction::Jump(11));
+        assert_eq!(state.selected, 4);
+    }
+
+    fn text(line: &Line) -> String {
+        line.chars.iter().map(|&(c, _)| c).collect()
+    }
+
+    #[test]
+    fn test_line_truncation() {
+        let mut line = Line::default();
+        line.push_highlighted("git log", &[0, 4]);
+        assert_eq!(line.chars[0].1, Style::Highlight);
+        assert_eq!(line.chars[1].1, Style::Plain);
+        assert_eq!(line.chars[4].1, Style::Highlight);
+
+        line.truncate(7);
+        assert_eq!(text(&line), "git log");
+        line.truncate(5);
+        assert_eq!(text(&line), "git …");
+        assert_eq!(line.width(), 5);
+
+        // Line breaks and tabs would move the cursor
+        let mut line = Line::default();
+        line.push("a\n\tb", Style::Plain);
+        assert_eq!(text(&line), "a↵ b");
+
+        let mut line = Line::default();
+        line.push("Search: cargo", Style::Plain);
+        line.truncate_start(6);
+        assert_eq!(text(&line), "…cargo");
+    }
+
+    #[test]
+    fn test_truncation_uses_display_width() {
+        // Every character takes two columns
+        let mut line = Line::default();
+        line.push("日本語の", Style::Plain);
+        assert_eq!(line.width(), 8);
+
+        line.truncate(6);
+        assert_eq!(text(&line), "日本…");
+        assert_eq!(line.width(), 5);
+
+        let mut line = Line::default();
+        line.push("日本語の", Style::Plain);
+        line.truncate_start(4);
+        assert_eq!(text(&line), "…の");
+
+        let mut line = Line::default();
+        line.push("abc", Style::Plain);
+        line.truncate(0);
+        assert_eq!(line.width(), 0);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut line = Line::default();
+        line.push("abcdefg", Style::Plain);
+        let lines: Vec<String> = line.wrap(3).iter().map(text).collect();
+        assert_eq!(lines, ["abc", "def", "g"]);
+
+        // Wide characters move to the next line rather than being split
+        let mut line = Line::default();
+        line.push("a日本", Style::Plain);
+        let lines: Vec<String> = line.wrap(2).iter().map(text).collect();
+        assert_eq!(lines, ["a", "日", "本"]);
+    }
+
+    fn preview_picker(keymap: &Keymap, preview: Preview) -> Picker<'_> {
+        let item = PickerItem {
+            preview: vec!["Command: ls\n-la".to_string(), "ID: 1".to_string()],
+            ..PickerItem::new("ls")
+        };
+        Picker {
+            items: vec![item.clone(), item],
+            state: PickerState::new(2, false),
+            help: "",
+            keymap,
+            search: None,
+            height: Height::Full,
+            preview,
+            show_preview: true,
+            prompt: None,
+            status: None,
+            print: false,
+            typing: true,
+            pending: Vec::new(),
+            last_click: None,
+        }
+    }
+
+    #[test]
+    fn test_preview_layout() {
+        let keymap = Keymap::default();
+
+        // Wide terminals show the preview next to the list
+        let mut picker = preview_picker(&keymap, Preview::Auto);
+        let layout = picker.layout((120, 20));
+        assert_eq!(layout.list_width, 60);
+        let pane = layout.preview.unwrap();
+        assert_eq!(pane.position, Preview::Right);
+        assert_eq!((pane.row, pane.column, pane.width), (0, 61, 59));
+        // Every line of a multi-line field is shown
+        assert_eq!(pane.height, 3);
+        assert_eq!(layout.help, 3);
+
+        // Narrow ones below it, after a separator
+        let layout = picker.layout((80, 20));
+        assert_eq!(layout.list_width, 80);
+        let pane = layout.preview.unwrap();
+        assert_eq!(pane.position, Preview::Bottom);
+        assert_eq!((pane.row, pane.column, pane.height), (3, 0, 3));
+        assert_eq!(layout.help, 6);
+
+        // The list keeps half of a short terminal
+        let layout = picker.layout((80, 5));
+        assert_eq!(layout.preview.unwrap().height, 1);
+        assert_eq!(picker.state.height, 2);
+
+        let mut picker = preview_picker(&keymap, Preview::Right);
+        assert_eq!(
+            picker.layout((40, 20)).preview.unwrap().position,
+            Preview::Right
+        );
+
+        picker.show_preview = false;
+        let layout = picker.layout((120, 20));
+        assert_eq!(layout.preview, None);
+        assert_eq!(layout.list_width, 120);
+        assert_eq!(layout.help, 2);
+    }
+
+    #[test]
+    fn test_parse_preview() {
+        assert_eq!("hidden".parse::<Preview>().unwrap(), Preview::Hidden);
+        assert_eq!("bottom".parse::<Preview>().unwrap(), Preview::Bottom);
+        assert!("left".parse::<Preview>().is_err());
+    }
+
+    /// Commands in memory, with the tags of each.
+    #[derive(Default)]
+    struct FakeCatalog {
+        commands: Vec<(String, Vec<String>)>,
+        found: Vec<usize>,
+    }
+
+    impl Catalog for FakeCatalog {
+        fn search(&mut self, query: &str) -> Result<Vec<PickerItem>> {
+            self.found = (0..self.commands.len())
+                .filter(|&i| self.commands[i].0.contains(query))
+                .collect();
+            Ok(self
+                .found
+                .iter()
+                .map(|&i| PickerItem {
+                    tags: self.commands[i].1.clone(),
+                    ..PickerItem::new(&self.commands[i].0)
+                })
+                .collect())
+        }
+
+        fn edit(&mut self, index: usize, edit: Edit) -> Result<()> {
+            let i = self.found[index];
+            match edit {
+                Edit::Delete => {
+                    self.commands.remove(i);
+                }
+                Edit::Command(cmd) if cmd.is_empty() => bail!("Command cannot be empty"),
+                Edit::Command(cmd) => self.commands[i].0 = cmd,
+                Edit::Tags(tags) => self.commands[i].1 = tags,
+                Edit::Description(_) => {}
+            }
+            Ok(())
+        }
+    }
+
+    fn search_picker<'a>(keymap: &'a Keymap, catalog: &'a mut FakeCatalog) -> Picker<'a> {
+        let mut picker = Picker {
+            items: Vec::new(),
+            state: PickerState::new(0, false),
+            help: "",
+            keymap,
+            search: Some(LiveSearch {
+                input: QueryInput::default(),
+                searched: None,
+                edited_at: None,
+                debounce: Duration::ZERO,
+                error: None,
+                catalog,
+            }),
+            height: Height::Full,
+            preview: Preview::Hidden,
+            show_preview: false,
+            prompt: None,
+            status: None,
+            print: false,
+            typing: true,
+            pending: Vec::new(),
+            last_click: None,
+        };
+        picker.refresh();
+        picker
+    }
+
+    fn type_text(picker: &mut Picker, text: &str) {
+        for c in text.chars() {
+            let prompt = picker.prompt.take().unwrap();
+            picker.answer(prompt, &KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    fn texts(picker: &Picker) -> Vec<String> {
+        picker.items.iter().map(|item| item.text.clone()).collect()
+    }
+
+    #[test]
+    fn test_delete_asks_for_confirmation() {
+        let mut keymap = Keymap::default();
+        keymap.bind(PickerAction::Accept, &parse_keys("enter").unwrap());
+        let mut catalog = FakeCatalog {
+            commands: ["ls", "pwd", "cd"]
+                .map(|cmd| (cmd.to_string(), Vec::new()))
+                .to_vec(),
+            ..Default::default()
+        };
+        let mut picker = search_picker(&keymap, &mut catalog);
+        picker.state.handle(PickerAction::Down);
+
+        // Anything but yes keeps the command
+        picker.ask(PickerAction::Delete);
+        type_text(&mut picker, "n");
+        assert!(picker.prompt.is_none());
+        assert_eq!(texts(&picker), ["ls", "pwd", "cd"]);
+
+        picker.ask(PickerAction::Delete);
+        type_text(&mut picker, "y");
+        assert_eq!(texts(&picker), ["ls", "cd"]);
+        assert_eq!(picker.status.as_deref(), Some("Command deleted"));
+        // The selection stays in place
+        assert_eq!(picker.state.selected, 1);
+
+        picker.ask(PickerAction::Delete);
+        type_text(&mut picker, "y");
+        assert_eq!(texts(&picker), ["ls"]);
+        assert_eq!(picker.state.selected, 0);
+    }
+
+    #[test]
+    fn test_edit_prompts() {
+        let mut keymap = Keymap::default();
+        keymap.bind(PickerAction::Accept, &parse_keys("enter").unwrap());
+        keymap.bind(PickerAction::Cancel, &parse_keys("esc").unwrap());
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        let mut catalog = FakeCatalog {
+            commands: vec![("ls".to_string(), vec!["files".to_string()])],
+            ..Default::default()
+        };
+        let mut picker = search_picker(&keymap, &mut catalog);
+
+        // Prompts start from the current value
+        picker.ask(PickerAction::Edit);
+        type_text(&mut picker, " -la");
+        let prompt = picker.prompt.take().unwrap();
+        picker.answer(prompt, &enter);
+        assert_eq!(texts(&picker), ["ls -la"]);
+        assert_eq!(picker.status.as_deref(), Some("Command updated"));
+
+        picker.ask(PickerAction::Tag);
+        assert_eq!(picker.prompt.as_ref().unwrap().input.text, "files");
+        type_text(&mut picker, ", list  dir");
+        let prompt = picker.prompt.take().unwrap();
+        picker.answer(prompt, &enter);
+        assert_eq!(picker.items[0].tags, ["files", "list", "dir"]);
+
+        // Cancelled prompts change nothing
+        picker.ask(PickerAction::Edit);
+        type_text(&mut picker, "x");
+        let prompt = picker.prompt.take().unwrap();
+        picker.answer(prompt, &esc);
+        assert!(picker.prompt.is_none());
+        assert_eq!(texts(&picker), ["ls -la"]);
+
+        // Errors are shown and the command is kept
+        picker.ask(PickerAction::Edit);
+        picker.prompt.as_mut().unwrap().input.text.clear();
+        let prompt = picker.prompt.take().unwrap();
+        picker.answer(prompt, &enter);
+        assert_eq!(
+            picker.status.as_deref(),
+            Some("Error: Command cannot be empty")
+        );
+        assert_eq!(texts(&picker), ["ls -la"]);
+    }
+
+    #[test]
+    fn test_prompt_edits() {
+        let prompt = |action, text: &str| Prompt {
+            action,
+            input: QueryInput {
+                text: text.to_string(),
+            },
+        };
+        assert_eq!(
+            prompt(PickerAction::Tag, " a,b  c, ").edit(),
+            Edit::Tags(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        );
+        assert_eq!(
+            prompt(PickerAction::Describe, "  ").edit(),
+            Edit::Description(None)
+        );
+        assert_eq!(
+            prompt(PickerAction::Describe, " List files ").edit(),
+            Edit::Description(Some("List files".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_ctrl_c_always_cancels() {
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        let mut keymap = Keymap::default();
+        let mut picker = preview_picker(&keymap, Preview::Hidden);
+        assert_eq!(picker.action(&ctrl_c), Some(PickerAction::Cancel));
+
+        // Even when bound to something else
+        keymap.bind(PickerAction::Copy, &parse_keys("ctrl-c").unwrap());
+        let mut picker = preview_picker(&keymap, Preview::Hidden);
+        assert_eq!(picker.action(&ctrl_c), Some(PickerAction::Cancel));
+
+        // And it cancels the picker from prompts, unlike the cancel keys
+        keymap.bind(PickerAction::Cancel, &parse_keys("esc").unwrap());
+        let mut picker = preview_picker(&keymap, Preview::Hidden);
+        picker.search = None;
+        for action in [PickerAction::Edit, PickerAction::Delete] {
+            let prompt = Prompt {
+                action,
+                input: QueryInput::default(),
+            };
+            assert_eq!(picker.answer(prompt, &ctrl_c), Step::Cancel);
+        }
+        let prompt = Prompt {
+            action: PickerAction::Edit,
+            input: QueryInput::default(),
+        };
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(picker.answer(prompt, &esc), Step::Continue);
+        assert!(picker.prompt.is_none());
+    }
+
+    #[test]
+    fn test_key_sequences() {
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        let mut keymap = Keymap::default();
+        keymap.bind(PickerAction::First, &parse_keys("g g").unwrap());
+        keymap.bind(PickerAction::Last, &parse_keys("G").unwrap());
+        let mut picker = preview_picker(&keymap, Preview::Hidden);
+
+        assert_eq!(picker.action(&key('g')), None);
+        assert_eq!(picker.action(&key('g')), Some(PickerAction::First));
+        // A key that breaks a sequence counts on its own
+        assert_eq!(picker.action(&key('g')), None);
+        assert_eq!(picker.action(&key('G')), Some(PickerAction::Last));
+        assert_eq!(picker.action(&key('x')), None);
+        assert!(picker.pending.is_empty());
+    }
+
+    #[test]
+    fn test_mouse() {
+        let click = |row| MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 2,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+        let keymap = Keymap::default();
+        let mut picker = preview_picker(&keymap, Preview::Hidden);
+        picker.show_preview = false;
+        let layout = picker.layout((80, 10));
+
+        assert_eq!(picker.mouse(click(1), layout), Step::Continue);
+        assert_eq!(picker.state.selected, 1);
+        // Clicking below the items does nothing
+        assert_eq!(picker.mouse(click(5), layout), Step::Continue);
+        assert_eq!(picker.state.selected, 1);
+        // Clicking the same item again picks it
+        assert_eq!(picker.mouse(click(1), layout), Step::Accept);
+
+        let scroll_up = MouseEvent {
+            kind: MouseEventKind::ScrollUp,
+            ..click(0)
+        };
+        picker.mouse(scroll_up, layout);
+        assert_eq!(picker.state.selected, 0);
+    }
+
+    #[test]
+    fn test_height() {
+        assert_eq!("full".parse::<Height>().unwrap(), Height::Full);
+        assert_eq!("15".parse::<Height>().unwrap(), Height::Lines(15));
+        assert_eq!("40%".parse::<Height>().unwrap(), Height::Percent(40));
+        for invalid in ["0", "0%", "101%", "-3", "half", "%"] {
+            assert!(invalid.parse::<Height>().is_err(), "{}", invalid);
+        }
+
+        assert_eq!(Height::Full.rows(30), 30);
+        assert_eq!(Height::Lines(15).rows(30), 15);
+        assert_eq!(Height::Lines(50).rows(30), 30);
+        assert_eq!(Height::Percent(40).rows(30), 12);
+        // Never too small for a query, an item and the help
+        assert_eq!(Height::Lines(1).rows(30), 3);
+        assert_eq!(Height::Percent(1).rows(30), 3);
+        // Terminals that report no rows still get one
+        assert_eq!(Height::Lines(15).rows(0), 1);
+        assert_eq!(Height::Percent(40).rows(0), 1);
+        assert_eq!(Height::Full.rows(0), 0);
+    }
+
+    #[test]
+    fn test_inline_rows_are_relative() {
+        let keymap = Keymap::default();
+        let mut picker = preview_picker(&keymap, Preview::Hidden);
+        picker.height = Height::Lines(10);
+        let layout = picker.layout((80, 10));
+        assert!(layout.inline);
+
+        let mut output = Vec::new();
+        layout.goto(&mut output, 0, 0).unwrap();
+        assert_eq!(output, b"\x1b8");
+        output.clear();
+        layout.goto(&mut output, 3, 2).unwrap();
+        assert_eq!(output, b"\x1b8\x1b[2B\x1b[3C");
+
+        picker.height = Height::Full;
+        let layout = picker.layout((80, 10));
+        output.clear();
+        layout.goto(&mut output, 3, 2).unwrap();
+        assert_eq!(output, b"\x1b[3;4H");
+    }
+}